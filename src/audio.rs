@@ -1,8 +1,12 @@
 pub mod common;
+pub mod dynamics;
 pub mod host;
+pub mod pitch;
 pub mod rechunker;
 pub mod stream;
 pub use common::*;
+pub use dynamics::*;
 pub use host::*;
+pub use pitch::*;
 pub use rechunker::*;
 pub use stream::*;
@@ -2,7 +2,10 @@ use derive_new::new;
 use getset::{Getters, Setters};
 use rustfft::num_complex::Complex32;
 use rustfft::num_traits::{FromPrimitive, Num, NumAssignOps, NumCast, NumOps};
+use rustfft::{FFTplanner, FFT};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Getters, Clone, Debug, new)]
 #[getset(get = "pub")]
@@ -100,10 +103,41 @@ impl<S: Sample> GenericDataChunk<S> {
     }
 }
 
+// A value describing a whole chunk of audio rather than its samples
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlValue {
+    // f0 in Hz, None when unvoiced
+    Pitch {
+        frequency: Option<f32>,
+        confidence: f32,
+    },
+    // speech presence, with the fraction of the detector features which voted for it
+    Voice {
+        active: bool,
+        score: f32,
+    },
+}
+
+// Control-rate counterpart of a chunk: one value per channel for `duration` samples
+#[derive(Getters, Clone, Debug, new)]
+#[getset(get = "pub")]
+pub struct ControlChunk {
+    values: Vec<ControlValue>,
+    metadata: AudioMetadata,
+    duration: usize,
+}
+
+impl ControlChunk {
+    pub fn value(&self, channel: usize) -> &ControlValue {
+        &self.values[channel]
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum DataChunk {
     Real(GenericDataChunk<f32>),
     Complex(GenericDataChunk<Complex32>),
+    Control(ControlChunk),
 }
 
 impl DataChunk {
@@ -111,6 +145,7 @@ impl DataChunk {
         match self {
             Self::Real(c) => c.metadata(),
             Self::Complex(c) => c.metadata(),
+            Self::Control(c) => c.metadata(),
         }
     }
 
@@ -118,6 +153,7 @@ impl DataChunk {
         match self {
             Self::Real(c) => c.duration(),
             Self::Complex(c) => c.duration(),
+            Self::Control(c) => c.duration(),
         }
     }
 
@@ -125,11 +161,12 @@ impl DataChunk {
         match self {
             Self::Real(c) => c.window_info(),
             Self::Complex(c) => c.window_info(),
+            Self::Control(_) => &None,
         }
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum WindowFunction {
     Hanning,
     Triangular,
@@ -143,3 +180,24 @@ pub struct WindowInfo {
     #[getset(get = "pub")]
     delay: usize,
 }
+
+// Transforms planned once per size and direction, for nodes which transform every chunk.
+#[derive(Default)]
+pub struct FftPlans {
+    plans: HashMap<(usize, bool), Arc<dyn FFT<f32>>>,
+}
+
+impl FftPlans {
+    pub fn get(&mut self, len: usize, inverse: bool) -> Arc<dyn FFT<f32>> {
+        self.plans
+            .entry((len, inverse))
+            .or_insert_with(|| FFTplanner::new(inverse).plan_fft(len))
+            .clone()
+    }
+}
+
+impl std::fmt::Debug for FftPlans {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_set().entries(self.plans.keys()).finish()
+    }
+}
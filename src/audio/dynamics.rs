@@ -0,0 +1,57 @@
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-10).log10()
+}
+
+pub fn db_to_amplitude(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+// one-pole coefficient which covers 1 - 1/e of a step in `ms`
+pub fn time_coefficient(ms: f32, sample_rate: usize) -> f32 {
+    if ms <= 0.0 {
+        0.0
+    } else {
+        (-1000.0 / (ms * sample_rate as f32)).exp()
+    }
+}
+
+// follows a signal with separate coefficients for rising and falling
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnvelopeFollower {
+    value: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(value: f32) -> Self {
+        Self { value }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn process(&mut self, x: f32, attack: f32, release: f32) -> f32 {
+        let coefficient = if x > self.value { attack } else { release };
+        self.value = x + coefficient * (self.value - x);
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn follower_reaches_time_constant() {
+        let sample_rate = 1000;
+        let attack = time_coefficient(10.0, sample_rate);
+        let mut follower = EnvelopeFollower::default();
+        for _ in 0..10 {
+            follower.process(1.0, attack, 0.0);
+        }
+        assert!((follower.value() - (1.0 - (-1.0f32).exp())).abs() < 1e-4);
+        // no release time drops immediately
+        assert_eq!(follower.process(0.0, attack, 0.0), 0.0);
+        assert!((amplitude_to_db(db_to_amplitude(-6.0)) + 6.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,396 @@
+// Fundamental frequency estimation based on YIN (de Cheveigné & Kawahara, 2002) and
+// probabilistic YIN (Mauch & Dixon, 2014).
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PITCH_RANGE: (f32, f32) = (60.0, 880.0);
+pub const DEFAULT_YIN_THRESHOLD: f32 = 0.15;
+pub const DEFAULT_VOICING_THRESHOLD: f32 = 0.3;
+pub const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchEstimate {
+    pub frequency: f32,
+    pub confidence: f32,
+}
+
+impl PitchEstimate {
+    pub fn period(&self, sample_rate: usize) -> f32 {
+        sample_rate as f32 / self.frequency
+    }
+}
+
+fn lag_range(len: usize, sample_rate: usize, range: (f32, f32)) -> Option<(usize, usize)> {
+    let min_lag = ((sample_rate as f32 / range.1).floor() as usize).max(2);
+    // at least half of the data is kept for the integration window
+    let max_lag = ((sample_rate as f32 / range.0).ceil() as usize).min(len / 2);
+    if min_lag + 2 > max_lag {
+        None
+    } else {
+        Some((min_lag, max_lag))
+    }
+}
+
+// cumulative mean normalized difference function for lags 0..=max_lag + 1
+fn cmndf(data: &[f32], max_lag: usize) -> Option<Vec<f32>> {
+    let lags = max_lag + 2;
+    if data.len() <= lags {
+        return None;
+    }
+    let window = data.len() - lags;
+    let energy = data[..window + max_lag].iter().map(|s| s * s).sum::<f32>();
+    if energy < 1e-8 * data.len() as f32 {
+        return None;
+    }
+    let mut c = vec![1.0; lags];
+    let mut running_sum = 0.0;
+    for tau in 1..lags {
+        let d = (0..window)
+            .map(|j| data[j] - data[j + tau])
+            .map(|d| d * d)
+            .sum::<f32>();
+        running_sum += d;
+        c[tau] = if running_sum > 0.0 {
+            d * tau as f32 / running_sum
+        } else {
+            1.0
+        };
+    }
+    Some(c)
+}
+
+fn parabolic_interpolation(c: &[f32], tau: usize) -> f32 {
+    if tau == 0 || tau + 1 >= c.len() {
+        return tau as f32;
+    }
+    let (a, b, d) = (c[tau - 1], c[tau], c[tau + 1]);
+    let denominator = a - 2.0 * b + d;
+    if denominator.abs() < f32::EPSILON {
+        tau as f32
+    } else {
+        tau as f32 + 0.5 * (a - d) / denominator
+    }
+}
+
+// the local minima of the difference function within the lag range, in increasing lag order
+fn troughs(c: &[f32], min_lag: usize, max_lag: usize) -> Vec<usize> {
+    (min_lag..=max_lag)
+        .filter(|tau| c[*tau] <= c[*tau - 1] && c[*tau] < c[*tau + 1])
+        .collect()
+}
+
+// Plain YIN: the first dip of the normalized difference below `threshold` within
+// the frequency range given in Hz.
+pub fn yin(
+    data: &[f32],
+    sample_rate: usize,
+    range: (f32, f32),
+    threshold: f32,
+) -> Option<PitchEstimate> {
+    let (min_lag, max_lag) = lag_range(data.len(), sample_rate, range)?;
+    let c = cmndf(data, max_lag)?;
+    let tau = troughs(&c, min_lag, max_lag)
+        .into_iter()
+        .find(|tau| c[*tau] < threshold)?;
+    Some(PitchEstimate {
+        frequency: sample_rate as f32 / parabolic_interpolation(&c, tau),
+        confidence: (1.0 - c[tau]).max(0.0).min(1.0),
+    })
+}
+
+const THRESHOLDS: usize = 100;
+const BINS_PER_SEMITONE: usize = 5;
+const MAX_JUMP: usize = 3 * BINS_PER_SEMITONE;
+const VOICING_SWITCH: f32 = 0.01;
+
+// Probabilistic YIN. Every threshold of a beta distribution votes for a dip of the
+// difference function, and the resulting pitch candidates are smoothed over time with
+// a hidden Markov model of voiced and unvoiced pitch states. Decoding is online: each
+// call reports the end state of the most probable path so far.
+#[derive(Clone, Debug)]
+pub struct ProbabilisticYin {
+    range: (f32, f32),
+    threshold_weights: Vec<f32>,
+    transition_weights: Vec<f32>,
+    log_probabilities: Vec<f32>,
+}
+
+impl ProbabilisticYin {
+    pub fn new(range: (f32, f32)) -> Self {
+        // beta distribution with a = 2, b = 18 (mean 0.1)
+        let threshold_weights = (0..THRESHOLDS)
+            .map(|i| (i + 1) as f32 / THRESHOLDS as f32)
+            .map(|s| s * (1.0 - s).powi(17))
+            .collect::<Vec<_>>();
+        let sum = threshold_weights.iter().sum::<f32>();
+        let threshold_weights = threshold_weights.iter().map(|w| w / sum).collect();
+
+        let transition_weights = (0..=2 * MAX_JUMP)
+            .map(|k| (MAX_JUMP + 1) as f32 - (k as f32 - MAX_JUMP as f32).abs())
+            .collect::<Vec<_>>();
+        let sum = transition_weights.iter().sum::<f32>();
+        let transition_weights = transition_weights.iter().map(|w| w / sum).collect();
+
+        Self {
+            range,
+            threshold_weights,
+            transition_weights,
+            log_probabilities: vec![],
+        }
+    }
+
+    pub fn range(&self) -> (f32, f32) {
+        self.range
+    }
+
+    pub fn set_range(&mut self, range: (f32, f32)) {
+        if self.range != range {
+            self.range = range;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.log_probabilities.clear();
+    }
+
+    fn bins(&self) -> usize {
+        let semitones = 12.0 * (self.range.1 / self.range.0).log2();
+        (semitones * BINS_PER_SEMITONE as f32).ceil().max(0.0) as usize + 1
+    }
+
+    fn bin(&self, frequency: f32) -> usize {
+        let bin = 12.0 * BINS_PER_SEMITONE as f32 * (frequency / self.range.0).log2();
+        (bin.round().max(0.0) as usize).min(self.bins() - 1)
+    }
+
+    fn bin_frequency(&self, bin: usize) -> f32 {
+        self.range.0 * 2.0f32.powf(bin as f32 / (12 * BINS_PER_SEMITONE) as f32)
+    }
+
+    // probabilities of each pitch bin being voiced, and the refined frequency of the
+    // most probable candidate in each bin
+    fn observe(&self, data: &[f32], sample_rate: usize) -> (Vec<f32>, Vec<Option<f32>>) {
+        let bins = self.bins();
+        let mut probabilities = vec![0.0; bins];
+        let mut frequencies = vec![None; bins];
+        let (min_lag, max_lag) = match lag_range(data.len(), sample_rate, self.range) {
+            Some(lags) => lags,
+            None => return (probabilities, frequencies),
+        };
+        let c = match cmndf(data, max_lag) {
+            Some(c) => c,
+            None => return (probabilities, frequencies),
+        };
+        let troughs = troughs(&c, min_lag, max_lag);
+        let mut trough_probabilities = vec![0.0; troughs.len()];
+        for (i, weight) in self.threshold_weights.iter().enumerate() {
+            let threshold = (i + 1) as f32 / THRESHOLDS as f32;
+            if let Some(k) = troughs.iter().position(|tau| c[*tau] < threshold) {
+                trough_probabilities[k] += weight;
+            }
+        }
+        let mut best = vec![0.0; bins];
+        for (tau, p) in troughs.iter().zip(trough_probabilities.iter()) {
+            if *p <= 0.0 {
+                continue;
+            }
+            let frequency = sample_rate as f32 / parabolic_interpolation(&c, *tau);
+            let b = self.bin(frequency);
+            probabilities[b] += p;
+            if *p > best[b] {
+                best[b] = *p;
+                frequencies[b] = Some(frequency);
+            }
+        }
+        (probabilities, frequencies)
+    }
+
+    pub fn estimate(&mut self, data: &[f32], sample_rate: usize) -> Option<PitchEstimate> {
+        let bins = self.bins();
+        let (voiced, frequencies) = self.observe(data, sample_rate);
+        let voiced_probability = voiced.iter().sum::<f32>().min(1.0);
+        let unvoiced = (1.0 - voiced_probability) / bins as f32;
+
+        if self.log_probabilities.len() != 2 * bins {
+            self.log_probabilities = vec![0.0; 2 * bins];
+        }
+        // states 0..bins are voiced, bins..2 * bins are unvoiced
+        let prev = &self.log_probabilities;
+        let mut next = vec![f32::NEG_INFINITY; 2 * bins];
+        for j in 0..bins {
+            let mut from_voiced = f32::NEG_INFINITY;
+            let mut from_unvoiced = f32::NEG_INFINITY;
+            let lo = j.saturating_sub(MAX_JUMP);
+            let hi = (j + MAX_JUMP).min(bins - 1);
+            for i in lo..=hi {
+                let w = self.transition_weights[i + MAX_JUMP - j].ln();
+                from_voiced = from_voiced.max(prev[i] + w);
+                from_unvoiced = from_unvoiced.max(prev[bins + i] + w);
+            }
+            let stay = (1.0 - VOICING_SWITCH).ln();
+            let switch = VOICING_SWITCH.ln();
+            next[j] = (from_voiced + stay).max(from_unvoiced + switch) + (voiced[j] + 1e-12).ln();
+            next[bins + j] =
+                (from_unvoiced + stay).max(from_voiced + switch) + (unvoiced + 1e-12).ln();
+        }
+        let max = next.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        for p in next.iter_mut() {
+            *p -= max;
+        }
+        let state = next
+            .iter()
+            .enumerate()
+            .fold(0, |best, (i, p)| if *p > next[best] { i } else { best });
+        self.log_probabilities = next;
+
+        if state >= bins {
+            return None;
+        }
+        Some(PitchEstimate {
+            frequency: frequencies[state].unwrap_or(self.bin_frequency(state)),
+            confidence: voiced_probability,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    Custom,
+}
+
+impl Scale {
+    // whether each pitch class above the key belongs to the scale
+    pub fn degrees(&self, custom: &[bool; 12]) -> [bool; 12] {
+        let intervals: &[usize] = match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Custom => return *custom,
+        };
+        let mut degrees = [false; 12];
+        for i in intervals.iter() {
+            degrees[*i] = true;
+        }
+        degrees
+    }
+}
+
+// MIDI note numbers, A4 = 69 = 440 Hz
+pub fn frequency_to_note(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+pub fn note_to_frequency(note: f32) -> f32 {
+    440.0 * 2.0f32.powf((note - 69.0) / 12.0)
+}
+
+// The frequency of the note nearest to `frequency` among the scale degrees above `key`
+// (0 = C), or None when the scale is empty.
+pub fn quantize_pitch(frequency: f32, key: usize, degrees: &[bool; 12]) -> Option<f32> {
+    let note = frequency_to_note(frequency);
+    let nearest = note.round() as i32;
+    (nearest - 6..=nearest + 6)
+        .filter(|n| degrees[(n - key as i32).rem_euclid(12) as usize])
+        .map(|n| n as f32)
+        .fold(None, |best: Option<f32>, n| match best {
+            Some(b) if (b - note).abs() <= (n - note).abs() => Some(b),
+            _ => Some(n),
+        })
+        .map(note_to_frequency)
+}
+
+// The shift in semitones which moves the scale note nearest to `frequency` by `steps`
+// scale degrees, e.g. a third above is 2 steps.
+pub fn scale_interval(frequency: f32, key: usize, degrees: &[bool; 12], steps: i32) -> Option<f32> {
+    let from = frequency_to_note(quantize_pitch(frequency, key, degrees)?).round() as i32;
+    let in_scale = |n: i32| degrees[(n - key as i32).rem_euclid(12) as usize];
+    let mut to = from;
+    for _ in 0..steps.abs() {
+        to += steps.signum();
+        while !in_scale(to) {
+            to += steps.signum();
+        }
+    }
+    Some((to - from) as f32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tone(frequency: f32, sample_rate: usize, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| 2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32)
+            .map(|t| 0.2 * t.sin() + 0.5 * (2.0 * t).sin() + 0.2 * (3.0 * t).sin())
+            .collect()
+    }
+
+    #[test]
+    fn yin_is_sample_rate_independent() {
+        for sample_rate in [44100, 48000].iter() {
+            let data = tone(220.0, *sample_rate, 2048);
+            let p = yin(&data, *sample_rate, (60.0, 800.0), 0.15).unwrap();
+            assert!((p.frequency - 220.0).abs() < 2.0, "{:?}", p);
+            assert!(p.confidence > 0.8);
+        }
+    }
+
+    #[test]
+    fn yin_rejects_silence() {
+        assert_eq!(yin(&vec![0.0; 2048], 44100, (60.0, 800.0), 0.15), None);
+    }
+
+    #[test]
+    fn probabilistic_yin_tracks_pitch() {
+        let mut pyin = ProbabilisticYin::new((60.0, 800.0));
+        for frequency in [200.0, 205.0, 210.0, 215.0].iter() {
+            let data = tone(*frequency, 44100, 2048);
+            let p = pyin.estimate(&data, 44100).unwrap();
+            assert!((p.frequency - frequency).abs() < 2.0, "{:?}", p);
+        }
+        assert_eq!(pyin.estimate(&vec![0.0; 2048], 44100), None);
+    }
+
+    #[test]
+    fn quantize_pitch_to_scale() {
+        let major = Scale::Major.degrees(&[false; 12]);
+        // a flat C#4 snaps down to C4 in C major, and A4 stays put
+        let c4 = note_to_frequency(60.0);
+        let q = quantize_pitch(note_to_frequency(60.8), 0, &major).unwrap();
+        assert!((q - c4).abs() < 0.01, "{}", q);
+        let q = quantize_pitch(442.0, 0, &major).unwrap();
+        assert!((q - 440.0).abs() < 0.01, "{}", q);
+        // in D major, C is not in the scale but C# is
+        let q = quantize_pitch(note_to_frequency(60.3), 2, &major).unwrap();
+        assert!((q - note_to_frequency(61.0)).abs() < 0.01, "{}", q);
+        assert_eq!(quantize_pitch(440.0, 0, &[false; 12]), None);
+    }
+
+    #[test]
+    fn scale_intervals() {
+        let major = Scale::Major.degrees(&[false; 12]);
+        // a third above C is a major third, above E a minor third
+        assert_eq!(
+            scale_interval(note_to_frequency(60.0), 0, &major, 2),
+            Some(4.0)
+        );
+        assert_eq!(
+            scale_interval(note_to_frequency(64.0), 0, &major, 2),
+            Some(3.0)
+        );
+        assert_eq!(
+            scale_interval(note_to_frequency(60.0), 0, &major, -1),
+            Some(-1.0)
+        );
+        assert_eq!(
+            scale_interval(note_to_frequency(60.0), 0, &major, 7),
+            Some(12.0)
+        );
+    }
+}
@@ -15,6 +15,7 @@ pub fn format_chunk_channel(chunk: DataChunk, out_channels: usize) -> DataChunk
         DataChunk::Complex(chunk) => {
             DataChunk::Complex(format_chunk_channel_generic(chunk, out_channels))
         }
+        DataChunk::Control(chunk) => DataChunk::Control(chunk),
     }
 }
 
@@ -26,6 +27,7 @@ pub fn format_chunk_sample_rate(chunk: DataChunk, out_sample_rate: usize) -> Dat
         DataChunk::Complex(chunk) => {
             DataChunk::Complex(format_chunk_sample_rate_generic(chunk, out_sample_rate))
         }
+        DataChunk::Control(chunk) => DataChunk::Control(chunk),
     }
 }
 
@@ -153,3 +155,54 @@ impl Rechunker {
         }
     }
 }
+
+// Buffers a secondary input, such as a sidechain, so that it can be taken in the durations
+// in which the main input arrives, keeping its channels as they are.
+#[derive(Debug, Default)]
+pub struct ChannelRechunker {
+    buffer: Vec<VecDeque<f32>>,
+    metadata: Option<AudioMetadata>,
+}
+
+impl ChannelRechunker {
+    pub fn feed_chunk(&mut self, chunk: GenericDataChunk<f32>) {
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        // samples left over in another format are dropped
+        let compatible = match &self.metadata {
+            Some(metadata) => {
+                *metadata.channels() == channels && *metadata.sample_rate() == sample_rate
+            }
+            None => false,
+        };
+        if !compatible {
+            self.buffer = vec![VecDeque::new(); channels];
+            self.metadata = Some(chunk.metadata().clone());
+        }
+        for (c, buffer) in self.buffer.iter_mut().enumerate() {
+            buffer.extend(chunk.samples(c).iter());
+        }
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.buffer.first().map(|b| b.len()).unwrap_or(0)
+    }
+
+    pub fn pull_chunk(&mut self, duration: usize) -> Option<GenericDataChunk<f32>> {
+        if self.buffered() < duration {
+            return None;
+        }
+        let metadata = self.metadata.clone()?;
+        let samples = self
+            .buffer
+            .iter_mut()
+            .map(|b| b.drain(..duration).collect())
+            .collect();
+        Some(GenericDataChunk::new(samples, metadata, duration, None))
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.metadata = None;
+    }
+}
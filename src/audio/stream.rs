@@ -1,26 +1,56 @@
 pub mod aggregate;
 pub mod arithmetic;
+pub mod compressor;
 pub mod dewindower;
+pub mod equalizer;
 pub mod filter;
 pub mod formantshifter;
+pub mod frequencyshifter;
 pub mod ft;
+pub mod gate;
 pub mod graph;
+pub mod harmonizer;
 pub mod identity;
+pub mod limiter;
 pub mod node;
+pub mod noise;
+pub mod noisereduction;
+pub mod oscillator;
 pub mod phasevocoder;
+pub mod pitchtracker;
 pub mod psola;
 pub mod replicator;
+pub mod ringmodulator;
+#[cfg(test)]
+mod testutil;
+pub mod timestretch;
+pub mod vad;
+pub mod vocoder;
 pub mod windower;
 pub use aggregate::*;
 pub use arithmetic::*;
+pub use compressor::*;
 pub use dewindower::*;
+pub use equalizer::*;
 pub use filter::*;
 pub use formantshifter::*;
+pub use frequencyshifter::*;
 pub use ft::*;
+pub use gate::*;
 pub use graph::*;
+pub use harmonizer::*;
 pub use identity::*;
+pub use limiter::*;
 pub use node::*;
+pub use noise::*;
+pub use noisereduction::*;
+pub use oscillator::*;
 pub use phasevocoder::*;
+pub use pitchtracker::*;
 pub use psola::*;
 pub use replicator::*;
+pub use ringmodulator::*;
+pub use timestretch::*;
+pub use vad::*;
+pub use vocoder::*;
 pub use windower::*;
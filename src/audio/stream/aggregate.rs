@@ -47,6 +47,14 @@ impl AggregateNode {
         if chunks.len() == 0 {
             return None;
         }
+        let is_control = chunks.iter().any(|c| match c {
+            DataChunk::Control(_) => true,
+            _ => false,
+        });
+        if is_control {
+            eprintln!("incompatible input {}: {}", file!(), line!());
+            return None;
+        }
         let is_real = chunks
             .iter()
             .map(|c| match c {
@@ -63,6 +71,7 @@ impl AggregateNode {
                     .map(|s| Complex32::from_f32(*s).unwrap())
                     .collect::<Vec<_>>(),
                 DataChunk::Complex(c) => c.flattened_data(),
+                DataChunk::Control(_) => unreachable!(),
             })
             .collect::<Vec<_>>();
         let l = samples[0].len();
@@ -102,6 +111,9 @@ impl NodeTrait for AggregateNode {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         loop {
             let chunks = self
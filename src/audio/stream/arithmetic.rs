@@ -48,6 +48,10 @@ impl ArithmeticNode {
     }
 
     pub fn process_chunk(&self, chunk: DataChunk) -> DataChunk {
+        if let DataChunk::Control(_) = chunk {
+            eprintln!("incompatible input {}: {}", file!(), line!());
+            return chunk;
+        }
         let samples = match &chunk {
             DataChunk::Real(chunk) => chunk
                 .flattened_data()
@@ -55,6 +59,7 @@ impl ArithmeticNode {
                 .map(|s| Complex32::from_f32(*s).unwrap())
                 .collect::<Vec<_>>(),
             DataChunk::Complex(chunk) => chunk.flattened_data(),
+            DataChunk::Control(_) => unreachable!(),
         }
         .iter()
         .map(|s| match self.op {
@@ -83,6 +88,7 @@ impl ArithmeticNode {
                 new_chunk.set_window_info(chunk.window_info().clone());
                 DataChunk::Complex(new_chunk)
             }
+            DataChunk::Control(_) => unreachable!(),
         }
     }
 }
@@ -91,6 +97,9 @@ impl NodeTrait for ArithmeticNode {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
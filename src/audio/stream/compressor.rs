@@ -0,0 +1,195 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+const HISTORY_LENGTH: usize = 256;
+const DETECTOR_RELEASE_MS: f32 = 10.0;
+
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct CompressorNode {
+    io: NodeIo,
+    id: NodeId,
+    // dB
+    threshold: f32,
+    ratio: f32,
+    // width of the soft knee around the threshold in dB
+    knee: f32,
+    // ms
+    attack: f32,
+    release: f32,
+    // dB
+    makeup: f32,
+    #[serde(skip)]
+    detector: EnvelopeFollower,
+    // gain reduction in dB, positive
+    #[serde(skip)]
+    reduction: EnvelopeFollower,
+    // lowest gain of each chunk in dB, oldest first
+    #[serde(skip)]
+    gain_history: Vec<f32>,
+}
+
+impl HasNodeIo for CompressorNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl CompressorNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            threshold: -20.0,
+            ratio: 4.0,
+            knee: 6.0,
+            attack: 10.0,
+            release: 100.0,
+            makeup: 0.0,
+            detector: EnvelopeFollower::default(),
+            reduction: EnvelopeFollower::default(),
+            gain_history: vec![],
+        }
+    }
+
+    pub fn threshold_mut(&mut self) -> &mut f32 {
+        &mut self.threshold
+    }
+
+    pub fn ratio_mut(&mut self) -> &mut f32 {
+        &mut self.ratio
+    }
+
+    pub fn knee_mut(&mut self) -> &mut f32 {
+        &mut self.knee
+    }
+
+    pub fn attack_mut(&mut self) -> &mut f32 {
+        &mut self.attack
+    }
+
+    pub fn release_mut(&mut self) -> &mut f32 {
+        &mut self.release
+    }
+
+    pub fn makeup_mut(&mut self) -> &mut f32 {
+        &mut self.makeup
+    }
+
+    pub fn gain_history(&self) -> &[f32] {
+        &self.gain_history
+    }
+
+    // static curve: output level for an input level, both in dB
+    pub fn output_level(&self, level: f32) -> f32 {
+        let (threshold, knee) = (self.threshold, self.knee.max(0.0));
+        let slope = 1.0 / self.ratio.max(1.0) - 1.0;
+        let over = level - threshold;
+        if 2.0 * over <= -knee {
+            level
+        } else if 2.0 * over.abs() <= knee {
+            level + slope * (over + knee / 2.0).powi(2) / (2.0 * knee)
+        } else {
+            level + slope * over
+        }
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let detector_release = time_coefficient(DETECTOR_RELEASE_MS, sample_rate);
+        let attack = time_coefficient(self.attack, sample_rate);
+        let release = time_coefficient(self.release, sample_rate);
+        let gains = (0..*chunk.duration())
+            .map(|t| {
+                let peak = (0..channels)
+                    .map(|c| chunk.samples(c)[t].abs())
+                    .fold(0.0, f32::max);
+                let level = amplitude_to_db(self.detector.process(peak, 0.0, detector_release));
+                let reduction = level - self.output_level(level);
+                self.makeup - self.reduction.process(reduction, attack, release)
+            })
+            .collect::<Vec<_>>();
+
+        self.gain_history
+            .push(gains.iter().cloned().fold(self.makeup, f32::min) - self.makeup);
+        if self.gain_history.len() > HISTORY_LENGTH {
+            self.gain_history.remove(0);
+        }
+        let samples = (0..channels)
+            .map(|c| {
+                chunk
+                    .samples(c)
+                    .iter()
+                    .zip(gains.iter())
+                    .map(|(s, g)| s * db_to_amplitude(*g))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            *chunk.duration(),
+            chunk.window_info().clone(),
+        )))
+    }
+}
+
+impl NodeTrait for CompressorNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compresses_above_threshold() {
+        let mut compressor = CompressorNode::new();
+        *compressor.knee_mut() = 0.0;
+        assert_eq!(compressor.output_level(-30.0), -30.0);
+        assert_eq!(compressor.output_level(0.0), -15.0);
+
+        let input = (0..48000)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect::<Vec<_>>();
+        let chunk = DataChunk::Real(
+            GenericDataChunk::from_flat_sata(&input, AudioMetadata::new(1, 48000)).unwrap(),
+        );
+        let output = match compressor.process_chunk(chunk) {
+            Some(DataChunk::Real(chunk)) => chunk.samples(0).to_vec(),
+            _ => panic!(),
+        };
+        let peak = output[24000..].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!((amplitude_to_db(peak) + 15.0).abs() < 1.0, "{}", peak);
+    }
+}
@@ -106,6 +106,9 @@ impl NodeTrait for Dewindower {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
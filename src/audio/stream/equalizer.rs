@@ -0,0 +1,336 @@
+use super::super::common::*;
+use super::node::*;
+use getset::Getters;
+use rustfft::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BandType {
+    LowShelf,
+    HighShelf,
+    Peaking,
+    LowPass,
+    HighPass,
+    Notch,
+    // constant 0 dB peak gain
+    BandPass,
+}
+
+impl BandType {
+    pub fn has_gain(&self) -> bool {
+        match self {
+            BandType::LowShelf | BandType::HighShelf | BandType::Peaking => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BiquadCoefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl BiquadCoefficients {
+    // magnitude of the frequency response
+    pub fn response(&self, frequency: f32, sample_rate: f32) -> f32 {
+        let w = 2.0 * std::f32::consts::PI * frequency / sample_rate;
+        let z1 = Complex32::from_polar(&1.0, &-w);
+        let z2 = Complex32::from_polar(&1.0, &(-2.0 * w));
+        let numerator = z1 * self.b1 + z2 * self.b2 + self.b0;
+        let denominator = z1 * self.a1 + z2 * self.a2 + 1.0;
+        (numerator / denominator).norm()
+    }
+}
+
+// transposed direct form II
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BiquadState {
+    z1: f32,
+    z2: f32,
+}
+
+impl BiquadState {
+    pub fn process(&mut self, c: &BiquadCoefficients, x: f32) -> f32 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EqBand {
+    pub band_type: BandType,
+    // Hz
+    pub frequency: f32,
+    // dB, only for shelves and peaking bands
+    pub gain: f32,
+    pub q: f32,
+    // per channel, so that the state goes with the band when bands are removed or reordered
+    #[serde(skip)]
+    states: Vec<BiquadState>,
+}
+
+impl EqBand {
+    pub fn new(band_type: BandType, frequency: f32) -> Self {
+        Self {
+            band_type,
+            frequency,
+            gain: 0.0,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+            states: vec![],
+        }
+    }
+
+    // from the Audio EQ Cookbook by R. Bristow-Johnson
+    pub fn coefficients(&self, sample_rate: f32) -> BiquadCoefficients {
+        let frequency = self.frequency.max(1.0).min(sample_rate * 0.499);
+        let w0 = 2.0 * std::f32::consts::PI * frequency / sample_rate;
+        let (sin, cos) = (w0.sin(), w0.cos());
+        let alpha = sin / (2.0 * self.q.max(0.01));
+        let a = 10.0f32.powf(self.gain / 40.0);
+        let shelf = 2.0 * a.sqrt() * alpha;
+        let (b0, b1, b2, a0, a1, a2) = match self.band_type {
+            BandType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BandType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BandType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BandType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BandType::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BandType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            BandType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+        };
+        BiquadCoefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct EqualizerNode {
+    io: NodeIo,
+    id: NodeId,
+    bands: Vec<EqBand>,
+    #[serde(skip)]
+    sample_rate: Option<usize>,
+}
+
+impl HasNodeIo for EqualizerNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl EqualizerNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            bands: vec![EqBand::new(BandType::Peaking, 1000.0)],
+            sample_rate: None,
+        }
+    }
+
+    pub fn bands(&self) -> &[EqBand] {
+        &self.bands
+    }
+
+    pub fn bands_mut(&mut self) -> &mut Vec<EqBand> {
+        &mut self.bands
+    }
+
+    // sample rate of the last chunk, or a typical one before any audio has arrived
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate.unwrap_or(44100)
+    }
+
+    // magnitude of the whole stack in dB at each frequency
+    pub fn response_db(&self, frequencies: &[f32]) -> Vec<f32> {
+        let sample_rate = self.sample_rate() as f32;
+        let coefficients = self
+            .bands
+            .iter()
+            .map(|b| b.coefficients(sample_rate))
+            .collect::<Vec<_>>();
+        frequencies
+            .iter()
+            .map(|f| {
+                let magnitude = coefficients
+                    .iter()
+                    .map(|c| c.response(*f, sample_rate))
+                    .product::<f32>();
+                20.0 * magnitude.max(1e-6).log10()
+            })
+            .collect()
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) if chunk.window_info().is_none() => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let reset = self.sample_rate != Some(sample_rate);
+        self.sample_rate = Some(sample_rate);
+        let mut samples = (0..channels)
+            .map(|c| chunk.samples(c).to_vec())
+            .collect::<Vec<_>>();
+        // the bands are in series, so each can filter the whole chunk in turn
+        for band in self.bands.iter_mut() {
+            if reset || band.states.len() != channels {
+                band.states = vec![BiquadState::default(); channels];
+            }
+            let coefficients = band.coefficients(sample_rate as f32);
+            for (state, samples) in band.states.iter_mut().zip(samples.iter_mut()) {
+                for x in samples.iter_mut() {
+                    *x = state.process(&coefficients, *x);
+                }
+            }
+        }
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            *chunk.duration(),
+            None,
+        )))
+    }
+}
+
+impl NodeTrait for EqualizerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filter(node: &mut EqualizerNode, input: &[f32], chunk_size: usize) -> Vec<f32> {
+        let mut output = vec![];
+        for chunk in input.chunks(chunk_size) {
+            let chunk = DataChunk::Real(
+                GenericDataChunk::from_flat_sata(chunk, AudioMetadata::new(1, 48000)).unwrap(),
+            );
+            if let Some(DataChunk::Real(chunk)) = node.process_chunk(chunk) {
+                output.extend_from_slice(chunk.samples(0));
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn peaking_band_boosts_its_frequency() {
+        let input = (0..48000)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect::<Vec<_>>();
+        let mut node = EqualizerNode::new();
+        node.bands_mut()[0].gain = 6.0;
+        let output = filter(&mut node, &input, 512);
+        let peak = output[24000..].iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!((peak - 10.0f32.powf(6.0 / 20.0)).abs() < 0.01, "{}", peak);
+        assert!((node.response_db(&[1000.0])[0] - 6.0).abs() < 0.01);
+
+        // the filter state carries over between chunks
+        let mut whole = EqualizerNode::new();
+        whole.bands_mut()[0].gain = 6.0;
+        let expected = filter(&mut whole, &input, input.len());
+        for (a, b) in output.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn removing_a_band_keeps_the_others_state() {
+        let input = (0..9600)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect::<Vec<_>>();
+        let (before, after) = input.split_at(4800);
+        let mut peak = EqBand::new(BandType::Peaking, 1000.0);
+        peak.gain = 6.0;
+        let mut shelf = EqBand::new(BandType::LowShelf, 300.0);
+        shelf.gain = -6.0;
+
+        let mut node = EqualizerNode::new();
+        *node.bands_mut() = vec![peak.clone(), shelf.clone()];
+        filter(&mut node, before, 512);
+        node.bands_mut().remove(0);
+        let output = filter(&mut node, after, 512);
+
+        // the shelf alone, fed what it received from the peaking band before the removal
+        let mut first = EqualizerNode::new();
+        *first.bands_mut() = vec![peak];
+        let peaked = filter(&mut first, before, 512);
+        let mut second = EqualizerNode::new();
+        *second.bands_mut() = vec![shelf];
+        filter(&mut second, &peaked, 512);
+        let expected = filter(&mut second, after, 512);
+        for (a, b) in output.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-5, "{} {}", a, b);
+        }
+    }
+}
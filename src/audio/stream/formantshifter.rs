@@ -11,19 +11,175 @@ pub struct Shift {
     pub to: f32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FormantMode {
+    // the whole frequency axis is scaled by `warp`
+    Warp,
+    // piecewise linear mapping through `shifts`
+    Shifts,
+}
+
+impl Default for FormantMode {
+    fn default() -> Self {
+        FormantMode::Shifts
+    }
+}
+
+// rough averages of F1 to F4 in Hz
+const MALE_FORMANTS: [f32; 4] = [500.0, 1500.0, 2500.0, 3500.0];
+const FEMALE_FORMANTS: [f32; 4] = [580.0, 1750.0, 2900.0, 4000.0];
+const CHILD_FORMANTS: [f32; 4] = [680.0, 2000.0, 3300.0, 4500.0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormantPreset {
+    MaleToFemale,
+    FemaleToMale,
+    MaleToChild,
+}
+
+pub const FORMANT_PRESETS: [FormantPreset; 3] = [
+    FormantPreset::MaleToFemale,
+    FormantPreset::FemaleToMale,
+    FormantPreset::MaleToChild,
+];
+
+impl FormantPreset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FormantPreset::MaleToFemale => "Male to female",
+            FormantPreset::FemaleToMale => "Female to male",
+            FormantPreset::MaleToChild => "Male to child",
+        }
+    }
+
+    pub fn shifts(&self) -> Vec<Shift> {
+        let (from, to) = match self {
+            FormantPreset::MaleToFemale => (MALE_FORMANTS, FEMALE_FORMANTS),
+            FormantPreset::FemaleToMale => (FEMALE_FORMANTS, MALE_FORMANTS),
+            FormantPreset::MaleToChild => (MALE_FORMANTS, CHILD_FORMANTS),
+        };
+        from.iter()
+            .zip(to.iter())
+            .map(|(from, to)| Shift {
+                from: *from,
+                to: *to,
+            })
+            .collect()
+    }
+}
+
 #[derive(Getters, Serialize, Deserialize, Debug)]
 pub struct FormantShifter {
     io: NodeIo,
     id: NodeId,
     shifts: Vec<Shift>,
-    #[serde(skip)]
-    prev_unwrapped_phases: Vec<Vec<f32>>,
+    #[serde(default)]
+    mode: FormantMode,
+    #[serde(default = "default_warp")]
+    warp: f32,
+    // cepstral coefficients below this quefrency make up the envelope
+    #[serde(default = "default_lifter_ms")]
+    lifter_ms: f32,
     #[serde(skip)]
     prev_envelope: Vec<f32>,
     #[serde(skip)]
     prev_delta_f: f32,
     #[serde(skip)]
     prev_duration: Option<usize>,
+    #[serde(skip)]
+    fft_plans: FftPlans,
+}
+
+fn default_warp() -> f32 {
+    1.0
+}
+
+fn default_lifter_ms() -> f32 {
+    1.5
+}
+
+const TRUE_ENVELOPE_ITERATIONS: usize = 16;
+
+fn lifter(log_magnitude: &[f32], lifter: usize, fft_plans: &mut FftPlans) -> Vec<f32> {
+    let n = log_magnitude.len();
+    let mut spectrum = log_magnitude
+        .iter()
+        .map(|m| Complex32::new(*m, 0.0))
+        .collect::<Vec<_>>();
+    let mut cepstrum = vec![Complex32::zero(); n];
+    fft_plans.get(n, true).process(&mut spectrum, &mut cepstrum);
+    for (q, c) in cepstrum.iter_mut().enumerate() {
+        if q > lifter && q + lifter < n {
+            *c = Complex32::zero();
+        }
+    }
+    fft_plans
+        .get(n, false)
+        .process(&mut cepstrum, &mut spectrum);
+    spectrum.iter().map(|s| s.re / n as f32).collect()
+}
+
+// smooth magnitude envelope of a spectrum, by cepstral liftering. The liftered log spectrum
+// is repeatedly raised to the spectrum so that it rests on the harmonics rather than
+// averaging them with the valleys between them (the "true envelope").
+fn spectral_envelope(
+    spectrum: &[Complex32],
+    lifter_length: usize,
+    fft_plans: &mut FftPlans,
+) -> Vec<f32> {
+    // a floor 60 dB below the peak keeps near-silent bins from dragging the envelope down
+    let floor = spectrum.iter().map(|s| s.norm()).fold(0.0, f32::max) * 1e-3;
+    let log_magnitude = spectrum
+        .iter()
+        .map(|s| s.norm().max(floor).max(1e-9).ln())
+        .collect::<Vec<_>>();
+    let mut envelope = lifter(&log_magnitude, lifter_length, fft_plans);
+    for _ in 0..TRUE_ENVELOPE_ITERATIONS {
+        let raised = log_magnitude
+            .iter()
+            .zip(envelope.iter())
+            .map(|(m, e)| m.max(*e))
+            .collect::<Vec<_>>();
+        envelope = lifter(&raised, lifter_length, fft_plans);
+    }
+    envelope.iter().map(|e| e.exp()).collect()
+}
+
+// the frequency whose envelope ends up at `frequency`, interpolating linearly between the
+// shifts, which are anchored at 0 and at Nyquist
+pub fn source_frequency(shifts: &[Shift], frequency: f32, nyquist: f32) -> f32 {
+    let mut anchors = vec![Shift { from: 0.0, to: 0.0 }];
+    anchors.extend(
+        shifts
+            .iter()
+            .filter(|s| s.to > 0.0 && s.to < nyquist)
+            .cloned(),
+    );
+    anchors.push(Shift {
+        from: nyquist,
+        to: nyquist,
+    });
+    anchors.sort_by(|a, b| a.to.partial_cmp(&b.to).unwrap());
+    let frequency = frequency.max(0.0).min(nyquist);
+    let upper = anchors
+        .iter()
+        .position(|s| s.to >= frequency)
+        .unwrap_or(anchors.len() - 1)
+        .max(1);
+    let (a, b) = (&anchors[upper - 1], &anchors[upper]);
+    let from = if b.to > a.to {
+        a.from + (frequency - a.to) / (b.to - a.to) * (b.from - a.from)
+    } else {
+        b.from
+    };
+    from.max(0.0).min(nyquist)
+}
+
+fn interpolate(values: &[f32], position: f32) -> f32 {
+    let i = (position.max(0.0) as usize).min(values.len() - 1);
+    let j = (i + 1).min(values.len() - 1);
+    let t = (position - i as f32).max(0.0).min(1.0);
+    values[i] * (1.0 - t) + values[j] * t
 }
 
 impl HasNodeIo for FormantShifter {
@@ -40,11 +196,14 @@ impl FormantShifter {
         Self {
             io: NodeIo::new(),
             id: NodeId::new(),
-            prev_unwrapped_phases: vec![],
             shifts: vec![],
+            mode: FormantMode::default(),
+            warp: default_warp(),
+            lifter_ms: default_lifter_ms(),
             prev_envelope: vec![],
             prev_delta_f: 1.0,
             prev_duration: None,
+            fft_plans: FftPlans::default(),
         }
     }
 
@@ -55,6 +214,33 @@ impl FormantShifter {
         &mut self.shifts
     }
 
+    pub fn mode(&self) -> FormantMode {
+        self.mode
+    }
+    pub fn mode_mut(&mut self) -> &mut FormantMode {
+        &mut self.mode
+    }
+
+    pub fn warp_mut(&mut self) -> &mut f32 {
+        &mut self.warp
+    }
+
+    pub fn apply_preset(&mut self, preset: FormantPreset) {
+        self.shifts = preset.shifts();
+        self.mode = FormantMode::Shifts;
+    }
+
+    fn source_frequency(&self, frequency: f32, nyquist: f32) -> f32 {
+        match self.mode {
+            FormantMode::Warp => (frequency / self.warp.max(0.01)).min(nyquist),
+            FormantMode::Shifts => source_frequency(&self.shifts, frequency, nyquist),
+        }
+    }
+
+    pub fn lifter_ms_mut(&mut self) -> &mut f32 {
+        &mut self.lifter_ms
+    }
+
     pub fn prev_envelope(&self) -> &[f32] {
         &self.prev_envelope
     }
@@ -68,19 +254,10 @@ impl FormantShifter {
 
     pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
         let channels = *chunk.metadata().channels();
-        while self.prev_unwrapped_phases.len() < channels {
-            self.prev_unwrapped_phases.push(vec![]);
-        }
-        for p in self.prev_unwrapped_phases.iter_mut() {
-            while p.len() < *chunk.duration() {
-                p.push(0.0);
-            }
-        }
-
         let mut incompatible = false;
         let samples = (0..channels)
             .map(|c| match &chunk {
-                DataChunk::Real(_) => {
+                DataChunk::Real(_) | DataChunk::Control(_) => {
                     eprintln!("incompatible input {}: {}", file!(), line!());
                     incompatible = true;
                     vec![]
@@ -92,67 +269,37 @@ impl FormantShifter {
             return None;
         }
 
-        let mut unwrapped_phases = vec![vec![]; channels];
-        for c in 0..channels {
-            let duration = samples[c].len();
-            for i in 0..duration {
-                let mut prev_unwrapped_phase = self.prev_unwrapped_phases[c][i];
-                if prev_unwrapped_phase.is_nan() {
-                    prev_unwrapped_phase = 0.0;
-                }
-                let pi = 3.141592;
-                let phase = samples[c][i].arg() % (2.0 * pi);
-                let prev_phase = prev_unwrapped_phase % (2.0 * pi);
-                let unwrapped_phase = prev_unwrapped_phase + phase - prev_phase
-                    + if phase - prev_phase < -pi {
-                        2.0 * pi
-                    } else if phase - prev_phase > pi {
-                        -2.0 * pi
-                    } else {
-                        0.0
-                    };
-                unwrapped_phases[c].push(unwrapped_phase);
-            }
-        }
-
-        self.prev_envelope = samples[0].iter().map(|s| s.norm()).collect::<Vec<_>>();
         let duration = *chunk.duration();
-        let d_f = *chunk.metadata().sample_rate() as f32 / duration as f32;
+        let sample_rate = *chunk.metadata().sample_rate() as f32;
+        let d_f = sample_rate / duration as f32;
+        let lifter = ((self.lifter_ms * sample_rate / 1000.0) as usize)
+            .max(1)
+            .min((duration / 2).max(1) - 1);
+        let fft_plans = &mut self.fft_plans;
+        let envelopes = samples
+            .iter()
+            .map(|s| spectral_envelope(s, lifter, fft_plans))
+            .collect::<Vec<_>>();
+        if let Some(envelope) = envelopes.first() {
+            self.prev_envelope = envelope.clone();
+        }
         self.prev_delta_f = d_f;
         self.prev_duration = Some(*chunk.duration());
 
-        let mut scaled = vec![vec![]; channels];
+        let nyquist = sample_rate / 2.0;
+        let mut scaled = samples.clone();
         for c in 0..channels {
-            for i in 0..duration / 2 {
-                let to_freq = i as f32 * d_f;
-                let shifts = self.closest_shift_triplet_by_to_freq(to_freq);
-                let from_freq = if to_freq == shifts[1].to {
-                    to_freq
-                } else if to_freq < shifts[1].to {
-                    (to_freq - shifts[0].to) / (shifts[1].to - shifts[0].to)
-                        * (shifts[1].from - shifts[0].from)
-                        + shifts[0].from
-                } else {
-                    (to_freq - shifts[1].to) / (shifts[2].to - shifts[1].to)
-                        * (shifts[2].from - shifts[1].from)
-                        + shifts[1].from
-                };
-                let from_i = (from_freq / d_f).round() as usize;
-                if from_i < duration / 2 && from_i >= 0 {
-                    scaled[c].push(samples[c][from_i]);
-                } else {
-                    scaled[c].push(Complex32::zero());
+            for i in 0..=duration / 2 {
+                // only the envelope is moved, the excitation stays where it is
+                let from_freq = self.source_frequency(i as f32 * d_f, nyquist);
+                let gain = interpolate(&envelopes[c], from_freq / d_f) / envelopes[c][i];
+                scaled[c][i] = samples[c][i] * gain;
+                if i > 0 && i < duration - i {
+                    scaled[c][duration - i] = scaled[c][i].conj();
                 }
             }
-            for i in duration / 2..duration {
-                let mirrored_index = duration - i - 1;
-                let mirrored_sample = scaled[c][mirrored_index].conj();
-                scaled[c].push(mirrored_sample);
-            }
         }
 
-        self.prev_unwrapped_phases = unwrapped_phases;
-
         let new_chunk = GenericDataChunk::new(
             scaled,
             chunk.metadata().clone(),
@@ -167,42 +314,15 @@ impl FormantShifter {
         self.shifts
             .sort_by(|a, b| a.from.partial_cmp(&b.from).unwrap());
     }
-
-    fn closest_shift_triplet_by_to_freq(&self, f: f32) -> Vec<Shift> {
-        let prepend = Shift { from: 0.0, to: 0.0 };
-        let append = Shift {
-            from: 20000.0,
-            to: 20000.0,
-        };
-        let mut shifts = if self.shifts.len() == 0 {
-            return vec![prepend, Shift { from: f, to: f }, append];
-        } else {
-            let mut shifts = self.shifts.clone();
-            shifts.insert(0, prepend);
-            shifts.push(append);
-            shifts
-        };
-        let mut closest_i = None;
-        let mut closest_to_freq: Option<f32> = None;
-        for (i, shift) in shifts.iter().enumerate().skip(1).take(self.shifts.len()) {
-            if let Some(mut closest_to_freq) = closest_to_freq {
-                if (closest_to_freq - f).abs() > (shift.to - f).abs() {
-                    closest_to_freq = shift.to;
-                    closest_i = Some(i);
-                }
-            } else {
-                closest_to_freq = Some(shift.to);
-                closest_i = Some(i);
-            }
-        }
-        shifts[(closest_i.unwrap() - 1)..(closest_i.unwrap() + 2)].to_vec()
-    }
 }
 
 impl NodeTrait for FormantShifter {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
@@ -216,3 +336,115 @@ impl NodeTrait for FormantShifter {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn magnitude_peaks(spectrum: &[Complex32]) -> Vec<usize> {
+        let max = spectrum.iter().map(|s| s.norm()).fold(0.0, f32::max);
+        (1..spectrum.len() / 2 - 1)
+            .filter(|&i| {
+                let m = spectrum[i].norm();
+                m > spectrum[i - 1].norm() && m > spectrum[i + 1].norm() && m > max * 1e-4
+            })
+            .collect()
+    }
+
+    fn shift(from: f32, to: f32) -> Shift {
+        Shift { from, to }
+    }
+
+    #[test]
+    fn source_frequency_without_shifts_is_identity() {
+        for f in [0.0, 440.0, 1000.0, 8000.0].iter() {
+            assert_eq!(source_frequency(&[], *f, 8000.0), *f);
+        }
+    }
+
+    #[test]
+    fn source_frequency_interpolates_between_anchors() {
+        let shifts = [shift(1000.0, 2000.0)];
+        assert_eq!(source_frequency(&shifts, 2000.0, 8000.0), 1000.0);
+        assert_eq!(source_frequency(&shifts, 1000.0, 8000.0), 500.0);
+        assert_eq!(source_frequency(&shifts, 5000.0, 8000.0), 4500.0);
+        assert_eq!(source_frequency(&shifts, 8000.0, 8000.0), 8000.0);
+        // the upper anchor follows the sample rate
+        let expected = 1000.0 + 9025.0 * 21050.0 / 20050.0;
+        assert!((source_frequency(&shifts, 11025.0, 22050.0) - expected).abs() < 1e-2);
+    }
+
+    #[test]
+    fn source_frequency_orders_shifts_by_destination() {
+        let shifts = [shift(3000.0, 2500.0), shift(1000.0, 1500.0)];
+        assert_eq!(source_frequency(&shifts, 1500.0, 8000.0), 1000.0);
+        assert_eq!(source_frequency(&shifts, 2000.0, 8000.0), 2000.0);
+        assert_eq!(source_frequency(&shifts, 2500.0, 8000.0), 3000.0);
+        // shifts beyond Nyquist are ignored
+        let shifts = [shift(1000.0, 9000.0)];
+        assert_eq!(source_frequency(&shifts, 4000.0, 8000.0), 4000.0);
+    }
+
+    #[test]
+    fn presets_move_formants_both_ways() {
+        let up = FormantPreset::MaleToFemale.shifts();
+        let down = FormantPreset::FemaleToMale.shifts();
+        assert!(up.iter().all(|s| s.to > s.from));
+        assert!(down.iter().all(|s| s.to < s.from));
+        for f in [400.0, 1500.0, 3000.0].iter() {
+            let there = source_frequency(&up, *f, 8000.0);
+            assert!((source_frequency(&down, there, 8000.0) - f).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn shifting_formants_keeps_harmonics() {
+        let (n, sample_rate) = (512, 8000);
+        // 125 Hz harmonics under a single formant around 1 kHz, windowed like a Windower frame
+        let pi = std::f32::consts::PI;
+        let mut frame = (0..n)
+            .map(|t| {
+                let window = 0.5 - 0.5 * (2.0 * pi * t as f32 / n as f32).cos();
+                let s = (1..32)
+                    .map(|h| {
+                        let f = 125.0 * h as f32;
+                        let gain = (-((f - 1000.0) / 400.0).powi(2)).exp();
+                        gain * (2.0 * pi * f * t as f32 / sample_rate as f32).sin()
+                    })
+                    .sum::<f32>();
+                Complex32::new(s * window, 0.0)
+            })
+            .collect::<Vec<_>>();
+        let mut spectrum = vec![Complex32::zero(); n];
+        FftPlans::default()
+            .get(n, false)
+            .process(&mut frame, &mut spectrum);
+        let mut shifter = FormantShifter::new();
+        shifter.add_shift(Shift {
+            from: 1000.0,
+            to: 1450.0,
+        });
+        let chunk = DataChunk::Complex(GenericDataChunk::new(
+            vec![spectrum.clone()],
+            AudioMetadata::new(1, sample_rate),
+            n,
+            None,
+        ));
+        let output = match shifter.process_chunk(chunk) {
+            Some(DataChunk::Complex(chunk)) => chunk.samples(0).to_vec(),
+            _ => panic!(),
+        };
+        let peaks = magnitude_peaks(&output);
+        assert!(!peaks.is_empty());
+        assert!(peaks.iter().all(|i| i % 8 == 0), "{:?}", peaks);
+        let centroid = |s: &[Complex32]| {
+            let d_f = sample_rate as f32 / n as f32;
+            let total = s[..n / 2].iter().map(|s| s.norm()).sum::<f32>();
+            (0..n / 2)
+                .map(|i| i as f32 * d_f * s[i].norm())
+                .sum::<f32>()
+                / total
+        };
+        assert!(centroid(&output) > centroid(&spectrum) + 250.0);
+    }
+}
@@ -0,0 +1,199 @@
+use super::super::common::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+// all-pass coefficients of two paths whose outputs are 90 degrees apart over most of the band,
+// by Olli Niemitalo
+const PATH_A: [f32; 4] = [0.692_387_8, 0.936_065_4, 0.988_229_5, 0.998_748_8];
+const PATH_B: [f32; 4] = [0.402_192_1, 0.856_171_1, 0.972_291, 0.995_288_5];
+
+// y[n] = a^2 (x[n] + y[n-2]) - x[n-2]
+#[derive(Debug, Clone, Copy, Default)]
+struct AllPass {
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl AllPass {
+    fn process(&mut self, a: f32, x: f32) -> f32 {
+        let y = a * a * (x + self.y[1]) - self.x[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+// Splits a signal into a pair 90 degrees apart, which approximates it and its Hilbert transform.
+#[derive(Debug, Clone, Copy, Default)]
+struct Hilbert {
+    a: [AllPass; 4],
+    b: [AllPass; 4],
+    // the first path is delayed by a sample
+    a_delayed: f32,
+}
+
+impl Hilbert {
+    fn process(&mut self, x: f32) -> (f32, f32) {
+        let i = self.a_delayed;
+        self.a_delayed = self
+            .a
+            .iter_mut()
+            .zip(PATH_A.iter())
+            .fold(x, |x, (s, a)| s.process(*a, x));
+        let q = self
+            .b
+            .iter_mut()
+            .zip(PATH_B.iter())
+            .fold(x, |x, (s, a)| s.process(*a, x));
+        (i, q)
+    }
+}
+
+// Moves every frequency of the input by the same amount with single-sideband modulation.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct FrequencyShifterNode {
+    io: NodeIo,
+    id: NodeId,
+    // Hz, negative to shift downwards
+    shift: f32,
+    // 0 for the dry input, 1 for the shifted signal only
+    mix: f32,
+    #[serde(skip)]
+    phase: f64,
+    #[serde(skip)]
+    filters: Vec<Hilbert>,
+}
+
+impl HasNodeIo for FrequencyShifterNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl FrequencyShifterNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            shift: 200.0,
+            mix: 1.0,
+            phase: 0.0,
+            filters: vec![],
+        }
+    }
+
+    pub fn shift_mut(&mut self) -> &mut f32 {
+        &mut self.shift
+    }
+
+    pub fn mix_mut(&mut self) -> &mut f32 {
+        &mut self.mix
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) if chunk.window_info().is_none() => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate() as f64;
+        let duration = *chunk.duration();
+        if self.filters.len() != channels {
+            self.filters = vec![Hilbert::default(); channels];
+        }
+        let dt = self.shift as f64 / sample_rate;
+        let mix = self.mix.max(0.0).min(1.0);
+        let oscillator = (0..duration)
+            .map(|_| {
+                let phase = 2.0 * std::f64::consts::PI * self.phase;
+                self.phase = (self.phase + dt).rem_euclid(1.0);
+                (phase.cos() as f32, phase.sin() as f32)
+            })
+            .collect::<Vec<_>>();
+        let filters = &mut self.filters;
+        let samples = (0..channels)
+            .map(|c| {
+                chunk
+                    .samples(c)
+                    .iter()
+                    .zip(oscillator.iter())
+                    .map(|(x, (cos, sin))| {
+                        // q lags i by 90 degrees
+                        let (i, q) = filters[c].process(*x);
+                        (1.0 - mix) * x + mix * (i * cos + q * sin)
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            duration,
+            None,
+        )))
+    }
+}
+
+impl NodeTrait for FrequencyShifterNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testutil::*;
+    use super::*;
+
+    const SAMPLE_RATE: usize = 16000;
+
+    #[test]
+    fn shifts_a_tone_in_either_direction() {
+        let input = (0..SAMPLE_RATE)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect::<Vec<_>>();
+        for shift in [250.0, -250.0].iter() {
+            let mut node = FrequencyShifterNode::new();
+            *node.shift_mut() = *shift;
+            let mut output = vec![];
+            for samples in input.chunks(400) {
+                let chunk =
+                    GenericDataChunk::from_flat_sata(samples, AudioMetadata::new(1, SAMPLE_RATE))
+                        .unwrap();
+                match node.process_chunk(DataChunk::Real(chunk)) {
+                    Some(DataChunk::Real(chunk)) => output.extend_from_slice(chunk.samples(0)),
+                    _ => panic!(),
+                }
+            }
+            // after the filters settle
+            let output = &output[SAMPLE_RATE / 2..];
+            let shifted = magnitude(output, 1000.0 + shift, SAMPLE_RATE);
+            let mirrored = magnitude(output, 1000.0 - shift, SAMPLE_RATE);
+            assert!((shifted - 1.0).abs() < 0.05, "{} {}", shift, shifted);
+            assert!(mirrored < 0.02, "{} {}", shift, mirrored);
+            assert!(magnitude(output, 1000.0, SAMPLE_RATE) < 0.02);
+        }
+    }
+}
@@ -47,6 +47,10 @@ impl FourierTransform {
     }
 
     pub fn process_chunk(&self, chunk: DataChunk) -> DataChunk {
+        if let DataChunk::Control(_) = chunk {
+            eprintln!("incompatible input {}: {}", file!(), line!());
+            return chunk;
+        }
         let channels = *chunk.metadata().channels();
         let mut samples = match &chunk {
             DataChunk::Real(chunk) => (0..channels)
@@ -61,6 +65,7 @@ impl FourierTransform {
             DataChunk::Complex(chunk) => (0..channels)
                 .map(|c| chunk.samples(c).to_vec())
                 .collect::<Vec<_>>(),
+            DataChunk::Control(_) => unreachable!(),
         };
         let mut transformed = samples.clone();
         let mut planner = FFTplanner::new(self.inverse);
@@ -98,6 +103,9 @@ impl NodeTrait for FourierTransform {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
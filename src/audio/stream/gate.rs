@@ -0,0 +1,331 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::super::rechunker::ChannelRechunker;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const HISTORY_LENGTH: usize = 256;
+const DETECTOR_RELEASE_MS: f32 = 10.0;
+const MAX_REDUCTION_DB: f32 = -80.0;
+
+// Gates or expands the first input. The level is detected on the second input when it is
+// connected (sidechain), otherwise on the first, with all channels linked.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct NoiseGateNode {
+    io: NodeIo,
+    id: NodeId,
+    // dB
+    threshold: f32,
+    // downward expansion below the threshold; large ratios gate
+    ratio: f32,
+    // ms
+    attack: f32,
+    hold: f32,
+    release: f32,
+    // dB below the threshold at which an open gate closes
+    hysteresis: f32,
+    #[serde(skip)]
+    detector: EnvelopeFollower,
+    #[serde(skip)]
+    gain: EnvelopeFollower,
+    #[serde(skip)]
+    open: bool,
+    #[serde(skip)]
+    hold_left: usize,
+    // lowest gain of each chunk in dB, oldest first
+    #[serde(skip)]
+    gain_history: Vec<f32>,
+    // main input waiting for the sidechain to catch up
+    #[serde(skip)]
+    pending: VecDeque<DataChunk>,
+    #[serde(skip)]
+    sidechain: ChannelRechunker,
+}
+
+impl HasNodeIo for NoiseGateNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl NoiseGateNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            threshold: -50.0,
+            ratio: 10.0,
+            attack: 1.0,
+            hold: 50.0,
+            release: 100.0,
+            hysteresis: 6.0,
+            detector: EnvelopeFollower::default(),
+            gain: EnvelopeFollower::default(),
+            open: false,
+            hold_left: 0,
+            gain_history: vec![],
+            pending: VecDeque::new(),
+            sidechain: ChannelRechunker::default(),
+        }
+    }
+
+    pub fn threshold_mut(&mut self) -> &mut f32 {
+        &mut self.threshold
+    }
+
+    pub fn ratio_mut(&mut self) -> &mut f32 {
+        &mut self.ratio
+    }
+
+    pub fn attack_mut(&mut self) -> &mut f32 {
+        &mut self.attack
+    }
+
+    pub fn hold_mut(&mut self) -> &mut f32 {
+        &mut self.hold
+    }
+
+    pub fn release_mut(&mut self) -> &mut f32 {
+        &mut self.release
+    }
+
+    pub fn hysteresis_mut(&mut self) -> &mut f32 {
+        &mut self.hysteresis
+    }
+
+    pub fn gain_history(&self) -> &[f32] {
+        &self.gain_history
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk, key: Option<DataChunk>) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let duration = *chunk.duration();
+        let key = match key {
+            Some(DataChunk::Real(key)) if *key.duration() == duration => key,
+            Some(_) => {
+                eprintln!("incompatible sidechain {}: {}", file!(), line!());
+                chunk.clone()
+            }
+            None => chunk.clone(),
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let detector_release = time_coefficient(DETECTOR_RELEASE_MS, sample_rate);
+        let attack = time_coefficient(self.attack, sample_rate);
+        let release = time_coefficient(self.release, sample_rate);
+        let hold = (self.hold * sample_rate as f32 / 1000.0) as usize;
+        let close_threshold = self.threshold - self.hysteresis.max(0.0);
+
+        let mut gains = Vec::with_capacity(duration);
+        for t in 0..duration {
+            let peak = (0..*key.metadata().channels())
+                .map(|c| key.samples(c)[t].abs())
+                .fold(0.0, f32::max);
+            let level = amplitude_to_db(self.detector.process(peak, 0.0, detector_release));
+            if level > self.threshold {
+                self.open = true;
+                self.hold_left = hold;
+            } else if level < close_threshold {
+                if self.hold_left > 0 {
+                    self.hold_left -= 1;
+                } else {
+                    self.open = false;
+                }
+            }
+            let target = if self.open {
+                0.0
+            } else {
+                ((level - self.threshold) * (self.ratio.max(1.0) - 1.0)).max(MAX_REDUCTION_DB)
+            };
+            gains.push(self.gain.process(target.min(0.0), attack, release));
+        }
+
+        self.gain_history
+            .push(gains.iter().cloned().fold(0.0, f32::min));
+        if self.gain_history.len() > HISTORY_LENGTH {
+            self.gain_history.remove(0);
+        }
+        let samples = (0..channels)
+            .map(|c| {
+                chunk
+                    .samples(c)
+                    .iter()
+                    .zip(gains.iter())
+                    .map(|(s, g)| s * db_to_amplitude(*g))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            duration,
+            chunk.window_info().clone(),
+        )))
+    }
+}
+
+impl NodeTrait for NoiseGateNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().is_empty() {
+            return;
+        }
+        // both inputs are buffered, as their chunks need not arrive together or be as long
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            self.pending.push_back(chunk);
+        }
+        while let Some(key) = self.inputs().get(1).and_then(|p| p.try_recv().ok()) {
+            match key {
+                DataChunk::Real(key) => self.sidechain.feed_chunk(key),
+                _ => eprintln!("incompatible sidechain {}: {}", file!(), line!()),
+            }
+        }
+        let keyed = self
+            .inputs()
+            .get(1)
+            .map(|p| p.rx.is_some())
+            .unwrap_or(false);
+        if !keyed {
+            self.sidechain.clear();
+        }
+        while let Some(chunk) = self.pending.pop_front() {
+            let key = if keyed {
+                match self.sidechain.pull_chunk(*chunk.duration()) {
+                    Some(key) => Some(DataChunk::Real(key)),
+                    None => {
+                        self.pending.push_front(chunk);
+                        break;
+                    }
+                }
+            } else {
+                None
+            };
+            if let Some(chunk) = self.process_chunk(chunk, key) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+    fn add_input(&mut self) -> Result<&mut InputPort, Box<dyn std::error::Error>> {
+        if self.inputs().len() < 2 {
+            let id = self.id();
+            self.node_io_mut().inputs_mut().push(InputPort::new(id));
+            let l = self.inputs().len();
+            Ok(&mut self.inputs_mut()[l - 1])
+        } else {
+            Err(Box::new(PortAdditionError))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chunk(level: f32, len: usize) -> DataChunk {
+        DataChunk::Real(GenericDataChunk::new(
+            vec![vec![level; len]],
+            AudioMetadata::new(1, 1000),
+            len,
+            None,
+        ))
+    }
+
+    fn last_sample(chunk: Option<DataChunk>) -> f32 {
+        match chunk {
+            Some(DataChunk::Real(chunk)) => *chunk.samples(0).last().unwrap(),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn gate_opens_holds_and_closes() {
+        let mut gate = NoiseGateNode::new();
+        *gate.ratio_mut() = 100.0;
+        // noise below the threshold is cut
+        let quiet = db_to_amplitude(-60.0);
+        assert!(last_sample(gate.process_chunk(chunk(quiet, 500), None)) < quiet * 0.01);
+        // a loud signal opens it
+        assert!((last_sample(gate.process_chunk(chunk(0.5, 100), None)) - 0.5).abs() < 1e-3);
+        // within the hysteresis it stays open
+        let between = db_to_amplitude(-53.0);
+        let out = last_sample(gate.process_chunk(chunk(between, 500), None));
+        assert!((out - between).abs() < between * 0.01);
+        // below it, the hold time passes before it closes
+        let out = last_sample(gate.process_chunk(chunk(quiet, 30), None));
+        assert!((out - quiet).abs() < quiet * 0.01);
+        assert!(last_sample(gate.process_chunk(chunk(quiet, 500), None)) < quiet * 0.01);
+        assert!(*gate.gain_history().last().unwrap() < -40.0);
+    }
+
+    #[test]
+    fn sidechain_keys_the_gate() {
+        let mut gate = NoiseGateNode::new();
+        let quiet = db_to_amplitude(-60.0);
+        let out = last_sample(gate.process_chunk(chunk(quiet, 100), Some(chunk(0.5, 100))));
+        assert!((out - quiet).abs() < quiet * 0.01);
+    }
+
+    #[test]
+    fn sidechain_is_paired_sample_by_sample() {
+        let mut gate = NoiseGateNode::new();
+        let (main_tx, main_rx) = std::sync::mpsc::sync_channel(8);
+        let (key_tx, key_rx) = std::sync::mpsc::sync_channel(8);
+        let (out_tx, out_rx) = std::sync::mpsc::sync_channel(8);
+        gate.add_input().unwrap().rx = Some(main_rx);
+        gate.add_input().unwrap();
+        gate.add_output().unwrap().tx = Some(out_tx);
+        let quiet = db_to_amplitude(-60.0);
+
+        // without a sidechain the main input keys the gate, without waiting
+        main_tx.send(chunk(quiet, 500)).unwrap();
+        gate.run_once();
+        assert!(last_sample(out_rx.try_recv().ok()) < quiet * 0.01);
+
+        gate.inputs_mut()[1].rx = Some(key_rx);
+        main_tx.send(chunk(quiet, 100)).unwrap();
+        main_tx.send(chunk(quiet, 100)).unwrap();
+        gate.run_once();
+        // nothing comes out until the sidechain has arrived
+        assert!(out_rx.try_recv().is_err());
+
+        // silence for 100 samples, then a loud key, in chunks of other lengths
+        let mut key = vec![0.0; 300];
+        for k in key[100..].iter_mut() {
+            *k = 0.5;
+        }
+        for part in [&key[..60], &key[60..200], &key[200..]].iter() {
+            key_tx
+                .send(DataChunk::Real(GenericDataChunk::new(
+                    vec![part.to_vec()],
+                    AudioMetadata::new(1, 1000),
+                    part.len(),
+                    None,
+                )))
+                .unwrap();
+        }
+        gate.run_once();
+        let first = last_sample(out_rx.try_recv().ok());
+        let second = last_sample(out_rx.try_recv().ok());
+        assert!(first < quiet * 0.01, "{}", first);
+        assert!((second - quiet).abs() < quiet * 0.01, "{}", second);
+        assert!(out_rx.try_recv().is_err());
+    }
+}
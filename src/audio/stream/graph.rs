@@ -10,6 +10,61 @@ use std::{
     fmt::{Display, Formatter},
 };
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Subgraph {
+    nodes: Vec<Node>,
+    edges: HashMap<OutputPortId, InputPortId>,
+}
+
+impl Subgraph {
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &HashMap<OutputPortId, InputPortId> {
+        &self.edges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // the same nodes and edges under fresh node and port ids, and the new id of each node
+    pub fn with_fresh_ids(mut self) -> (Self, HashMap<NodeId, NodeId>) {
+        let mut node_ids = HashMap::new();
+        let mut input_ids = HashMap::new();
+        let mut output_ids = HashMap::new();
+        for node in self.nodes.iter() {
+            node_ids.insert(node.id(), NodeId::new());
+            for port in node.inputs().iter() {
+                input_ids.insert(port.id(), InputPortId::new());
+            }
+            for port in node.outputs().iter() {
+                output_ids.insert(port.id(), OutputPortId::new());
+            }
+        }
+        for node in self.nodes.iter_mut() {
+            let node_id = node_ids[&node.id()];
+            node.set_id(node_id);
+            for port in node.inputs_mut().iter_mut() {
+                port.reassign(input_ids[&port.id()], node_id);
+                port.output_id = port.output_id.and_then(|id| output_ids.get(&id).cloned());
+            }
+            for port in node.outputs_mut().iter_mut() {
+                port.reassign(output_ids[&port.id()], node_id);
+                port.input_id = port.input_id.and_then(|id| input_ids.get(&id).cloned());
+            }
+        }
+        // edges leaving the subgraph are not part of it
+        self.edges = self
+            .edges
+            .iter()
+            .flat_map(|(o, i)| Some((*output_ids.get(o)?, *input_ids.get(i)?)))
+            .collect();
+        (self, node_ids)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Graph {
     nodes: HashMap<NodeId, Arc<Mutex<Node>>>,
@@ -34,7 +89,7 @@ impl Error for CompatibilityError {
 }
 
 #[derive(Debug, Clone)]
-struct ExistenceError(&'static str);
+pub struct ExistenceError(&'static str);
 
 impl Display for ExistenceError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -48,6 +103,21 @@ impl Error for ExistenceError {
     }
 }
 
+#[derive(Debug, Clone)]
+struct DuplicationError(&'static str);
+
+impl Display for DuplicationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "the {} already exists", self.0)
+    }
+}
+
+impl Error for DuplicationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
 impl Graph {
     pub fn new() -> Self {
         Self {
@@ -166,6 +236,15 @@ impl Graph {
         self.search_identity_node_by_name("Output")
     }
 
+    pub fn is_io_node(&self, id: &NodeId) -> bool {
+        [self.input_node(), self.output_node()]
+            .iter()
+            .any(|n| match n {
+                Ok(n) => n.lock().unwrap().id() == *id,
+                Err(_) => false,
+            })
+    }
+
     pub fn is_input_port(&self, id: &InputPortId) -> bool {
         self.input_port_node_map.get(id).is_some()
     }
@@ -174,6 +253,14 @@ impl Graph {
         self.output_port_node_map.get(id).is_some()
     }
 
+    pub fn input_port_node(&self, id: &InputPortId) -> Option<NodeId> {
+        self.input_port_node_map.get(id).cloned()
+    }
+
+    pub fn output_port_node(&self, id: &OutputPortId) -> Option<NodeId> {
+        self.output_port_node_map.get(id).cloned()
+    }
+
     pub fn add_input(&mut self, node_id: &NodeId) -> Result<InputPortId, Box<dyn Error>> {
         let node = self.node(node_id)?;
         let id = node.lock().unwrap().add_input()?.id().clone();
@@ -188,6 +275,28 @@ impl Graph {
         Ok(id)
     }
 
+    // control chunks cannot be played, so the editor keeps them away from the output node
+    pub fn sends_control_to_output(&self, from_id: &OutputPortId, to_id: &InputPortId) -> bool {
+        let to_output = match (self.output_node(), self.input_port_node(to_id)) {
+            (Ok(output), Some(id)) => output.lock().unwrap().id() == id,
+            _ => false,
+        };
+        let from_node = self
+            .output_port_node(from_id)
+            .and_then(|id| self.node(&id).ok());
+        match from_node {
+            Some(node) if to_output => {
+                let node = node.lock().unwrap();
+                node.outputs()
+                    .iter()
+                    .position(|p| p.id() == *from_id)
+                    .map(|i| node.is_control_output(i))
+                    .unwrap_or(false)
+            }
+            _ => false,
+        }
+    }
+
     fn node_ids_without_inputs(&self) -> Vec<NodeId> {
         let mut s = Vec::new();
         for node in self.nodes.values() {
@@ -237,8 +346,25 @@ impl Graph {
             }
         }
 
-        for n in l.iter() {
-            self.node(n).unwrap().lock().unwrap().run_once();
+        // the input node runs first so that sources know how much to produce
+        let input = self.input_node().ok();
+        let input_id = input.as_ref().map(|n| n.lock().unwrap().id());
+        let ticks = match &input {
+            Some(node) => {
+                let mut node = node.lock().unwrap();
+                node.run_once();
+                match &*node {
+                    Node::Identity(node) => node.ticks().to_vec(),
+                    _ => vec![],
+                }
+            }
+            None => vec![],
+        };
+        for n in l.iter().filter(|n| Some(**n) != input_id) {
+            let node = self.node(n).unwrap();
+            let mut node = node.lock().unwrap();
+            node.tick(&ticks);
+            node.run_once();
         }
         Ok(())
     }
@@ -273,13 +399,94 @@ impl Graph {
             .collect::<Vec<_>>();
         for port in input_ids.iter() {
             self.detach_input_port(port);
+            self.input_port_node_map.remove(port);
         }
         for port in output_ids.iter() {
             self.detach_output_port(port);
+            self.output_port_node_map.remove(port);
         }
         self.nodes.remove(&id)
     }
 
+    pub fn subgraph(&self, ids: &[NodeId]) -> Result<Subgraph, Box<dyn Error>> {
+        let mut nodes = vec![];
+        for id in ids.iter() {
+            if self.is_io_node(id) {
+                continue;
+            }
+            let node = self.node(id)?;
+            let node = node.lock().unwrap();
+            // nodes hold channels, so they are copied through their serialized form
+            nodes.push(serde_json::from_value(serde_json::to_value(&*node)?)?);
+        }
+        let node_ids = nodes.iter().map(|n: &Node| n.id()).collect::<HashSet<_>>();
+        let edges = self
+            .edges
+            .iter()
+            .filter(|(o, i)| {
+                node_ids.contains(self.output_port_node_map.get(o).unwrap())
+                    && node_ids.contains(self.input_port_node_map.get(i).unwrap())
+            })
+            .map(|(o, i)| (*o, *i))
+            .collect();
+        Ok(Subgraph { nodes, edges })
+    }
+
+    pub fn insert(&mut self, subgraph: Subgraph) -> Result<Vec<NodeId>, Box<dyn Error>> {
+        // everything is checked first so that a failed insertion leaves the graph untouched
+        self.validate_subgraph(&subgraph)?;
+        let Subgraph { nodes, edges } = subgraph;
+        let mut ids = vec![];
+        for mut node in nodes.into_iter() {
+            let node_id = node.id();
+            for port in node.inputs_mut().iter_mut() {
+                port.output_id = None;
+                self.input_port_node_map.insert(port.id(), node_id);
+            }
+            for port in node.outputs_mut().iter_mut() {
+                port.input_id = None;
+                self.output_port_node_map.insert(port.id(), node_id);
+            }
+            self.add(node);
+            ids.push(node_id);
+        }
+        for (from_id, to_id) in edges.iter() {
+            self.connect_ports(from_id, to_id)?;
+        }
+        Ok(ids)
+    }
+
+    fn validate_subgraph(&self, subgraph: &Subgraph) -> Result<(), Box<dyn Error>> {
+        let mut node_ids = HashSet::new();
+        let mut input_ids = HashSet::new();
+        let mut output_ids = HashSet::new();
+        for node in subgraph.nodes.iter() {
+            if self.nodes.contains_key(&node.id()) || !node_ids.insert(node.id()) {
+                return Err(Box::new(DuplicationError("node")));
+            }
+            for port in node.inputs().iter() {
+                if self.is_input_port(&port.id()) || !input_ids.insert(port.id()) {
+                    return Err(Box::new(DuplicationError("port")));
+                }
+            }
+            for port in node.outputs().iter() {
+                if self.is_output_port(&port.id()) || !output_ids.insert(port.id()) {
+                    return Err(Box::new(DuplicationError("port")));
+                }
+            }
+        }
+        let mut connected_inputs = HashSet::new();
+        for (from_id, to_id) in subgraph.edges.iter() {
+            if !output_ids.contains(from_id) || !input_ids.contains(to_id) {
+                return Err(Box::new(ExistenceError("port")));
+            }
+            if !connected_inputs.insert(*to_id) {
+                return Err(Box::new(DuplicationError("edge")));
+            }
+        }
+        Ok(())
+    }
+
     pub fn node(&self, id: &NodeId) -> Result<Arc<Mutex<Node>>, ExistenceError> {
         if let Some(ref node) = self.nodes.get(&id) {
             Ok(Arc::clone(node))
@@ -437,4 +644,93 @@ mod test {
         g.disconnect_ports(&n1_out_id, &n2_in_id).unwrap();
         assert_eq!(g.edges().get(&n1_out_id), None);
     }
+
+    #[test]
+    fn subgraph_insert() {
+        let mut g = Graph::default();
+        let n1 = Node::Psola(PsolaNode::new(1.0));
+        let n2 = Node::Psola(PsolaNode::new(2.0));
+        let n1_id = n1.id();
+        let n2_id = n2.id();
+        g.add(n1);
+        g.add(n2);
+        let n1_out_id = g.add_output(&n1_id).unwrap();
+        let n2_in_id = g.add_input(&n2_id).unwrap();
+        g.connect_ports(&n1_out_id, &n2_in_id).unwrap();
+        let input_id = g.input_node().unwrap().lock().unwrap().id();
+
+        let s = g.subgraph(&[n1_id, n2_id, input_id]).unwrap();
+        assert_eq!(s.nodes().len(), 2);
+        assert_eq!(s.edges().len(), 1);
+        assert!(g.insert(s).is_err());
+
+        let s = g.subgraph(&[n1_id, n2_id]).unwrap();
+        let mut h = Graph::new();
+        let ids = h.insert(s).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(*h.edges().get(&n1_out_id).unwrap(), n2_in_id);
+        h.run_once().unwrap();
+
+        // a copy under fresh ids goes into the graph it came from
+        let (s, node_ids) = g.subgraph(&[n1_id, n2_id]).unwrap().with_fresh_ids();
+        let edges = g.edges().len();
+        let ids = g.insert(s).unwrap();
+        assert!(ids.contains(&node_ids[&n1_id]) && ids.contains(&node_ids[&n2_id]));
+        assert_eq!(g.edges().len(), edges + 1);
+        g.run_once().unwrap();
+    }
+
+    #[test]
+    fn control_outputs_are_told_apart() {
+        let mut g = Graph::default();
+        let tracker = Node::PitchTracker(PitchTrackerNode::new());
+        let psola = Node::Psola(PsolaNode::new(1.0));
+        let (tracker_id, psola_id) = (tracker.id(), psola.id());
+        g.add(tracker);
+        g.add(psola);
+        let tracker_out_id = g.add_output(&tracker_id).unwrap();
+        let psola_out_id = g.add_output(&psola_id).unwrap();
+        let psola_in_id = g.add_input(&psola_id).unwrap();
+        let output_in_id = g.output_node().unwrap().lock().unwrap().inputs()[0].id();
+        assert!(g.sends_control_to_output(&tracker_out_id, &output_in_id));
+        assert!(!g.sends_control_to_output(&tracker_out_id, &psola_in_id));
+        assert!(!g.sends_control_to_output(&psola_out_id, &output_in_id));
+    }
+
+    #[test]
+    fn failed_insert_leaves_graph_unchanged() {
+        let mut g = Graph::default();
+        let n1 = Node::Psola(PsolaNode::new(1.0));
+        let n2 = Node::Psola(PsolaNode::new(2.0));
+        let n1_id = n1.id();
+        let n2_id = n2.id();
+        g.add(n1);
+        g.add(n2);
+        let n1_out_id = g.add_output(&n1_id).unwrap();
+        let n2_in_id = g.add_input(&n2_id).unwrap();
+        g.connect_ports(&n1_out_id, &n2_in_id).unwrap();
+        let s = g.subgraph(&[n1_id, n2_id]).unwrap();
+
+        let mut h = Graph::default();
+        let n3 = Node::Psola(PsolaNode::new(1.0));
+        let n3_id = n3.id();
+        h.add(n3);
+        h.add_input(&n3_id).unwrap();
+        let mut bad = g.subgraph(&[n1_id, n2_id]).unwrap();
+        // an edge into a port which is not part of the subgraph
+        let n3_in_id = h.node(&n3_id).unwrap().lock().unwrap().inputs()[0].id();
+        bad.edges.insert(OutputPortId::new(), n3_in_id);
+        let (nodes, edges) = (h.nodes().len(), h.edges().len());
+        assert!(h.insert(bad).is_err());
+        assert_eq!(h.nodes().len(), nodes);
+        assert_eq!(h.edges().len(), edges);
+        assert!(h.node(&n1_id).is_err());
+
+        // the same nodes inserted twice
+        h.insert(s).unwrap();
+        let (nodes, edges) = (h.nodes().len(), h.edges().len());
+        assert!(h.insert(g.subgraph(&[n1_id, n2_id]).unwrap()).is_err());
+        assert_eq!(h.nodes().len(), nodes);
+        assert_eq!(h.edges().len(), edges);
+    }
 }
@@ -0,0 +1,325 @@
+use super::super::common::*;
+use super::super::pitch::*;
+use super::node::*;
+use super::psola::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum IntervalMode {
+    Semitones,
+    ScaleDegrees,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HarmonyVoice {
+    // semitones, or scale degrees above the sung note
+    pub interval: f32,
+    pub gain: f32,
+    // -1 (left) to 1 (right)
+    pub pan: f32,
+    // cents
+    pub detune: f32,
+}
+
+impl HarmonyVoice {
+    pub fn new(interval: f32) -> Self {
+        Self {
+            interval,
+            gain: 0.5,
+            pan: 0.0,
+            detune: 0.0,
+        }
+    }
+
+    // balance of a stereo pair; mono and other layouts are not panned
+    fn channel_gain(&self, channel: usize, channels: usize) -> f32 {
+        let pan = self.pan.max(-1.0).min(1.0);
+        match (channels, channel) {
+            (2, 0) => self.gain * (1.0 - pan).min(1.0),
+            (2, 1) => self.gain * (1.0 + pan).min(1.0),
+            _ => self.gain,
+        }
+    }
+}
+
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct HarmonizerNode {
+    io: NodeIo,
+    id: NodeId,
+    voices: Vec<HarmonyVoice>,
+    dry_gain: f32,
+    #[getset(get = "pub")]
+    interval_mode: IntervalMode,
+    // for scale degrees, 0 = C
+    #[getset(get = "pub")]
+    key: usize,
+    #[getset(get = "pub")]
+    scale: Scale,
+    custom_notes: [bool; 12],
+    min_frequency: f32,
+    max_frequency: f32,
+    #[serde(skip)]
+    engine: PsolaEngine,
+}
+
+impl HasNodeIo for HarmonizerNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl HarmonizerNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            voices: vec![HarmonyVoice::new(4.0), HarmonyVoice::new(7.0)],
+            dry_gain: 1.0,
+            interval_mode: IntervalMode::Semitones,
+            key: 0,
+            scale: Scale::Major,
+            custom_notes: [true; 12],
+            min_frequency: DEFAULT_PITCH_RANGE.0,
+            max_frequency: DEFAULT_PITCH_RANGE.1,
+            engine: PsolaEngine::new(),
+        }
+    }
+
+    pub fn voices_mut(&mut self) -> &mut Vec<HarmonyVoice> {
+        &mut self.voices
+    }
+
+    pub fn remove_voice(&mut self, voice: usize) {
+        self.voices.remove(voice);
+        self.engine.remove_voice(voice);
+    }
+
+    pub fn dry_gain_mut(&mut self) -> &mut f32 {
+        &mut self.dry_gain
+    }
+
+    pub fn interval_mode_mut(&mut self) -> &mut IntervalMode {
+        &mut self.interval_mode
+    }
+
+    pub fn key_mut(&mut self) -> &mut usize {
+        &mut self.key
+    }
+
+    pub fn scale_mut(&mut self) -> &mut Scale {
+        &mut self.scale
+    }
+
+    pub fn custom_notes_mut(&mut self) -> &mut [bool; 12] {
+        &mut self.custom_notes
+    }
+
+    pub fn min_frequency_mut(&mut self) -> &mut f32 {
+        &mut self.min_frequency
+    }
+
+    pub fn max_frequency_mut(&mut self) -> &mut f32 {
+        &mut self.max_frequency
+    }
+
+    fn pitch_range(&self) -> (f32, f32) {
+        let min = self.min_frequency.max(1.0);
+        (min, self.max_frequency.max(min * 2.0))
+    }
+
+    fn voice_ratio(&self, voice: &HarmonyVoice, estimate: Option<PitchEstimate>) -> f32 {
+        let semitones = match (self.interval_mode, estimate) {
+            (IntervalMode::Semitones, _) => voice.interval,
+            (IntervalMode::ScaleDegrees, Some(p)) => {
+                let degrees = self.scale.degrees(&self.custom_notes);
+                scale_interval(
+                    p.frequency,
+                    self.key,
+                    &degrees,
+                    voice.interval.round() as i32,
+                )
+                .unwrap_or(0.0)
+            }
+            (IntervalMode::ScaleDegrees, None) => 0.0,
+        };
+        2.0f32.powf(semitones / 12.0 + voice.detune / 1200.0)
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        // the engine is taken out while the voice parameters are borrowed
+        let mut engine = std::mem::replace(&mut self.engine, PsolaEngine::new());
+        let output = engine.process(
+            &chunk,
+            self.voices.len(),
+            self.pitch_range(),
+            DEFAULT_VOICING_THRESHOLD,
+            WindowFunction::Hanning,
+            |_, v, estimate| self.voice_ratio(&self.voices[v], estimate),
+        );
+        self.engine = engine;
+
+        let samples = (0..channels)
+            .map(|c| {
+                let mut mixed = output.dry[c]
+                    .iter()
+                    .map(|s| s * self.dry_gain)
+                    .collect::<Vec<_>>();
+                for (voice, shifted) in self.voices.iter().zip(output.voices[c].iter()) {
+                    let gain = voice.channel_gain(c, channels);
+                    for (m, s) in mixed.iter_mut().zip(shifted.iter()) {
+                        *m += s * gain;
+                    }
+                }
+                mixed
+            })
+            .collect();
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            *chunk.duration(),
+            chunk.window_info().clone(),
+        )))
+    }
+}
+
+impl NodeTrait for HarmonizerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    const CHUNK_SIZE: usize = 512;
+
+    // a stereo 220 Hz sine through the node, per channel
+    fn render(node: &mut HarmonizerNode, chunks: std::ops::Range<usize>) -> Vec<Vec<f32>> {
+        let mut output = vec![vec![]; 2];
+        for k in chunks {
+            let tone = (k * CHUNK_SIZE..(k + 1) * CHUNK_SIZE)
+                .map(|i| {
+                    0.5 * (2.0 * std::f32::consts::PI * 220.0 * i as f32 / SAMPLE_RATE as f32).sin()
+                })
+                .collect::<Vec<_>>();
+            let chunk = DataChunk::Real(GenericDataChunk::new(
+                vec![tone.clone(), tone],
+                AudioMetadata::new(2, SAMPLE_RATE),
+                CHUNK_SIZE,
+                None,
+            ));
+            match node.process_chunk(chunk) {
+                Some(DataChunk::Real(chunk)) => {
+                    for (c, o) in output.iter_mut().enumerate() {
+                        o.extend_from_slice(chunk.samples(c));
+                    }
+                }
+                _ => panic!(),
+            }
+        }
+        output
+    }
+
+    // past the latency and the start up of the pitch tracker
+    fn tail(samples: &[f32]) -> &[f32] {
+        &samples[samples.len() - 4096..]
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    fn single_voice(voice: HarmonyVoice) -> HarmonizerNode {
+        let mut node = HarmonizerNode::new();
+        *node.dry_gain_mut() = 0.0;
+        *node.voices_mut() = vec![voice];
+        node
+    }
+
+    #[test]
+    fn voices_sound_at_their_intervals() {
+        // a fifth in semitones, and a third above A3 in C major, which is C4
+        let mut fifth = single_voice(HarmonyVoice::new(7.0));
+        let mut third = single_voice(HarmonyVoice::new(2.0));
+        *third.interval_mode_mut() = IntervalMode::ScaleDegrees;
+        for (node, note) in [(&mut fifth, 64.0), (&mut third, 60.0)].iter_mut() {
+            let output = render(node, 0..80);
+            let p = yin(tail(&output[0]), SAMPLE_RATE, DEFAULT_PITCH_RANGE, 0.15).unwrap();
+            let error = frequency_to_note(p.frequency) - *note;
+            assert!(error.abs() < 0.1, "{} Hz", p.frequency);
+        }
+    }
+
+    #[test]
+    fn mix_follows_the_levels() {
+        let input_rms = 0.5 / 2.0f32.sqrt();
+
+        let mut dry = HarmonizerNode::new();
+        *dry.dry_gain_mut() = 0.25;
+        dry.voices_mut().clear();
+        let output = render(&mut dry, 0..40);
+        for samples in output.iter() {
+            let level = rms(tail(samples)) / input_rms;
+            assert!((level - 0.25).abs() < 0.01, "{}", level);
+        }
+
+        // half the default level, panned hard left so that nothing reaches the right
+        let reference = render(&mut single_voice(HarmonyVoice::new(7.0)), 0..80);
+        let mut voice = HarmonyVoice::new(7.0);
+        voice.gain = 0.25;
+        voice.pan = -1.0;
+        let mut wet = single_voice(voice);
+        let output = render(&mut wet, 0..80);
+        let left = rms(tail(&output[0])) / rms(tail(&reference[0]));
+        let right = rms(tail(&output[1]));
+        assert!((left - 0.5).abs() < 0.01, "{}", left);
+        assert!(right < 1e-6, "{}", right);
+    }
+
+    #[test]
+    fn removing_a_voice_keeps_the_others_state() {
+        let mut node = HarmonizerNode::new();
+        *node.dry_gain_mut() = 0.0;
+        render(&mut node, 0..40);
+        node.remove_voice(0);
+        let output = render(&mut node, 40..60);
+
+        // the fifth alone from the start
+        let mut expected = single_voice(HarmonyVoice::new(7.0));
+        render(&mut expected, 0..40);
+        let expected = render(&mut expected, 40..60);
+        for (a, b) in output[0].iter().zip(expected[0].iter()) {
+            assert!((a - b).abs() < 1e-5, "{} {}", a, b);
+        }
+    }
+}
@@ -10,6 +10,9 @@ pub struct IdentityNode {
     id: NodeId,
     #[serde(skip)]
     last_chunk: Option<DataChunk>,
+    // chunks passed in the latest run
+    #[serde(skip)]
+    ticks: Vec<Tick>,
 }
 
 impl HasNodeIo for IdentityNode {
@@ -28,6 +31,7 @@ impl IdentityNode {
             name,
             id: NodeId::new(),
             last_chunk: None,
+            ticks: vec![],
         }
     }
 
@@ -38,17 +42,29 @@ impl IdentityNode {
     pub fn last_chunk(&self) -> Option<&DataChunk> {
         self.last_chunk.as_ref()
     }
+
+    pub fn ticks(&self) -> &[Tick] {
+        &self.ticks
+    }
 }
 
 impl NodeTrait for IdentityNode {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
         }
+        self.ticks.clear();
         while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            self.ticks.push(Tick {
+                metadata: chunk.metadata().clone(),
+                duration: *chunk.duration(),
+            });
             self.last_chunk = Some(chunk.clone());
             for output in self.outputs().iter() {
                 let result = output.try_send(chunk.clone());
@@ -0,0 +1,261 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const HISTORY_LENGTH: usize = 256;
+// points between two samples at which the true peak is estimated
+const OVERSAMPLING: usize = 4;
+
+// cubic (Catmull-Rom) interpolation between p1 and p2
+fn interpolate(p: [f32; 4], t: f32) -> f32 {
+    let a = -0.5 * p[0] + 1.5 * p[1] - 1.5 * p[2] + 0.5 * p[3];
+    let b = p[0] - 2.5 * p[1] + 2.0 * p[2] - 0.5 * p[3];
+    let c = -0.5 * p[0] + 0.5 * p[2];
+    ((a * t + b) * t + c) * t + p[1]
+}
+
+// peak of the segment ending at p[2], including the estimated peaks between samples
+fn true_peak(p: [f32; 4]) -> f32 {
+    (1..OVERSAMPLING)
+        .map(|k| interpolate(p, k as f32 / OVERSAMPLING as f32).abs())
+        .fold(p[2].abs(), f32::max)
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    channels: usize,
+    sample_rate: usize,
+    lookahead: usize,
+    // input samples waiting to be output
+    history: Vec<VecDeque<f32>>,
+    // gains required over the last `lookahead` samples
+    required: VecDeque<f32>,
+    // minima of `required`, averaged so that the gain ramps down ahead of a peak
+    minima: VecDeque<f32>,
+    minima_sum: f64,
+    gain: f32,
+}
+
+impl LimiterState {
+    fn new(channels: usize, sample_rate: usize, lookahead: usize) -> Self {
+        let lookahead = lookahead.max(1);
+        Self {
+            channels,
+            sample_rate,
+            lookahead,
+            history: vec![vec![0.0; (lookahead + 1).max(4)].into(); channels],
+            required: vec![1.0; lookahead].into(),
+            minima: vec![1.0; lookahead].into(),
+            minima_sum: lookahead as f64,
+            gain: 1.0,
+        }
+    }
+
+    // takes one sample of each channel and returns the gain for the samples being output
+    fn push(&mut self, frame: &[f32], ceiling: f32, release: f32) -> f32 {
+        let mut peak = 0.0f32;
+        for (history, x) in self.history.iter_mut().zip(frame.iter()) {
+            history.pop_front();
+            history.push_back(*x);
+            let n = history.len();
+            let p = [
+                history[n - 4],
+                history[n - 3],
+                history[n - 2],
+                history[n - 1],
+            ];
+            peak = peak.max(true_peak(p));
+        }
+        let required = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+        self.required.pop_front();
+        self.required.push_back(required);
+        let minimum = self.required.iter().cloned().fold(1.0, f32::min);
+        self.minima_sum += minimum as f64 - self.minima.pop_front().unwrap() as f64;
+        self.minima.push_back(minimum);
+        let target = (self.minima_sum / self.lookahead as f64) as f32;
+
+        self.gain = if target < self.gain {
+            target
+        } else {
+            target + release * (self.gain - target)
+        };
+        self.gain
+    }
+
+    // the sample output along with the gain from `push`
+    fn delayed(&self, channel: usize) -> f32 {
+        let history = &self.history[channel];
+        history[history.len() - 1 - self.lookahead]
+    }
+}
+
+// Delays the input by the look-ahead so that the gain is already down when a peak arrives.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct LimiterNode {
+    io: NodeIo,
+    id: NodeId,
+    // dBTP
+    ceiling: f32,
+    // ms
+    lookahead: f32,
+    release: f32,
+    #[serde(skip)]
+    state: Option<LimiterState>,
+    #[serde(skip)]
+    gain_history: Vec<f32>,
+}
+
+impl HasNodeIo for LimiterNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl LimiterNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            ceiling: -1.0,
+            lookahead: 5.0,
+            release: 50.0,
+            state: None,
+            gain_history: vec![],
+        }
+    }
+
+    pub fn ceiling_mut(&mut self) -> &mut f32 {
+        &mut self.ceiling
+    }
+
+    pub fn lookahead_mut(&mut self) -> &mut f32 {
+        &mut self.lookahead
+    }
+
+    pub fn release_mut(&mut self) -> &mut f32 {
+        &mut self.release
+    }
+
+    pub fn gain_history(&self) -> &[f32] {
+        &self.gain_history
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) if chunk.window_info().is_none() => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let lookahead = (self.lookahead * sample_rate as f32 / 1000.0) as usize;
+        let compatible = match &self.state {
+            Some(state) => {
+                state.channels == channels
+                    && state.sample_rate == sample_rate
+                    && state.lookahead == lookahead.max(1)
+            }
+            None => false,
+        };
+        if !compatible {
+            self.state = Some(LimiterState::new(channels, sample_rate, lookahead));
+        }
+        let state = self.state.as_mut().unwrap();
+        let ceiling = db_to_amplitude(self.ceiling);
+        let release = time_coefficient(self.release, sample_rate);
+
+        let mut samples = vec![Vec::with_capacity(*chunk.duration()); channels];
+        let mut lowest = 1.0f32;
+        for t in 0..*chunk.duration() {
+            let frame = (0..channels)
+                .map(|c| chunk.samples(c)[t])
+                .collect::<Vec<_>>();
+            let gain = state.push(&frame, ceiling, release);
+            lowest = lowest.min(gain);
+            for (c, s) in samples.iter_mut().enumerate() {
+                s.push(state.delayed(c) * gain);
+            }
+        }
+
+        self.gain_history.push(amplitude_to_db(lowest));
+        if self.gain_history.len() > HISTORY_LENGTH {
+            self.gain_history.remove(0);
+        }
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            *chunk.duration(),
+            None,
+        )))
+    }
+}
+
+impl NodeTrait for LimiterNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_stays_below_ceiling() {
+        let sample_rate = 48000;
+        // bursts at 2.0 with inter-sample peaks, between quiet passages
+        let input = (0..sample_rate)
+            .map(|i| {
+                let burst = if (i / 4800) % 2 == 1 { 2.0 } else { 0.1 };
+                let phase = 2.0 * std::f32::consts::PI * 11025.0 * i as f32 / sample_rate as f32;
+                burst * (phase + 0.25 * std::f32::consts::PI).sin()
+            })
+            .collect::<Vec<_>>();
+        let mut limiter = LimiterNode::new();
+        let mut output = vec![];
+        for chunk in input.chunks(512) {
+            let chunk = DataChunk::Real(
+                GenericDataChunk::from_flat_sata(chunk, AudioMetadata::new(1, sample_rate))
+                    .unwrap(),
+            );
+            if let Some(DataChunk::Real(chunk)) = limiter.process_chunk(chunk) {
+                output.extend_from_slice(chunk.samples(0));
+            }
+        }
+        assert_eq!(output.len(), input.len());
+        let ceiling = db_to_amplitude(-1.0);
+        let peak = output
+            .windows(4)
+            .fold(0.0f32, |m, p| m.max(true_peak([p[0], p[1], p[2], p[3]])));
+        assert!(peak <= ceiling * 1.001, "{} > {}", peak, ceiling);
+        // quiet passages come back up after the release
+        let lookahead = 240;
+        let quiet = &output[4800 * 2 + lookahead + 2400..4800 * 3 + lookahead];
+        let quiet_peak = quiet.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(quiet_peak > 0.09, "{}", quiet_peak);
+    }
+}
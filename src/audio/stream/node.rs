@@ -1,4 +1,4 @@
-use crate::audio::common::DataChunk;
+use crate::audio::common::{AudioMetadata, DataChunk};
 use serde::{Deserialize, Serialize};
 use std::sync::mpsc::{Receiver, SyncSender};
 use uuid::Uuid;
@@ -43,6 +43,13 @@ impl NodeIo {
     }
 }
 
+// A chunk which entered the graph through the input node; sources produce in step with these
+#[derive(Debug, Clone)]
+pub struct Tick {
+    pub metadata: AudioMetadata,
+    pub duration: usize,
+}
+
 #[enum_dispatch]
 pub trait HasNodeIo {
     fn node_io(&self) -> &NodeIo;
@@ -52,6 +59,8 @@ pub trait HasNodeIo {
 #[enum_dispatch]
 pub trait NodeTrait: HasNodeIo {
     fn id(&self) -> NodeId;
+    // only for copies, before they are added to a graph
+    fn set_id(&mut self, id: NodeId);
     fn inputs(&self) -> &[InputPort] {
         self.node_io().inputs()
     }
@@ -79,6 +88,16 @@ pub trait NodeTrait: HasNodeIo {
         let l = self.outputs().len();
         Ok(&mut self.outputs_mut()[l - 1])
     }
+    // sources produce chunks from ticks and take no input
+    fn is_source(&self) -> bool {
+        false
+    }
+    // whether the output at `index` carries control chunks rather than audio
+    fn is_control_output(&self, _index: usize) -> bool {
+        false
+    }
+    // called by the graph before `run_once` with the chunks which entered it in this run
+    fn tick(&mut self, _ticks: &[Tick]) {}
     fn run_once(&mut self);
 }
 
@@ -97,6 +116,20 @@ pub enum Node {
     PhaseVocoder(PhaseVocoder),
     PeriodReplicator(PeriodReplicator),
     FormantShifter(FormantShifter),
+    PitchTracker(PitchTrackerNode),
+    Harmonizer(HarmonizerNode),
+    TimeStretch(TimeStretchNode),
+    Equalizer(EqualizerNode),
+    NoiseGate(NoiseGateNode),
+    Compressor(CompressorNode),
+    Limiter(LimiterNode),
+    NoiseReduction(NoiseReductionNode),
+    Vad(VadNode),
+    ChannelVocoder(ChannelVocoderNode),
+    Oscillator(OscillatorNode),
+    Noise(NoiseNode),
+    RingModulator(RingModulatorNode),
+    FrequencyShifter(FrequencyShifterNode),
 }
 
 #[derive(Debug, Clone)]
@@ -170,6 +203,12 @@ impl InputPort {
         self.id
     }
 
+    // a copied port gets an id of its own and belongs to the copied node
+    pub fn reassign(&mut self, id: InputPortId, node_id: NodeId) {
+        self.id = id;
+        self.node_id = node_id;
+    }
+
     pub fn try_recv(&self) -> Result<DataChunk, Box<dyn std::error::Error>> {
         match &self.rx {
             Some(rx) => Ok(rx.try_recv()?),
@@ -201,6 +240,12 @@ impl OutputPort {
         self.id
     }
 
+    // a copied port gets an id of its own and belongs to the copied node
+    pub fn reassign(&mut self, id: OutputPortId, node_id: NodeId) {
+        self.id = id;
+        self.node_id = node_id;
+    }
+
     pub fn try_send(&self, chunk: DataChunk) -> Result<(), Box<dyn std::error::Error>> {
         match &self.tx {
             Some(tx) => Ok(tx.try_send(chunk)?),
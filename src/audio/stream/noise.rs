@@ -0,0 +1,200 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum NoiseColor {
+    White,
+    // -3 dB per octave
+    Pink,
+    // -6 dB per octave
+    Brown,
+}
+
+#[derive(Debug, Clone)]
+struct NoiseGenerator {
+    // xorshift
+    state: u32,
+    pink: [f32; 7],
+    brown: f32,
+}
+
+impl NoiseGenerator {
+    fn new(seed: u32) -> Self {
+        Self {
+            state: seed.max(1),
+            pink: [0.0; 7],
+            brown: 0.0,
+        }
+    }
+
+    // uniform in [-1, 1)
+    fn white(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+
+    fn next(&mut self, color: NoiseColor) -> f32 {
+        let white = self.white();
+        match color {
+            NoiseColor::White => white,
+            // filter by Paul Kellett
+            NoiseColor::Pink => {
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            // leaky integration of white noise
+            NoiseColor::Brown => {
+                self.brown = (self.brown + 0.02 * white) / 1.02;
+                self.brown * 3.5
+            }
+        }
+    }
+}
+
+// Produces noise in step with the chunks entering the graph, independent in each channel.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct NoiseNode {
+    io: NodeIo,
+    id: NodeId,
+    color: NoiseColor,
+    // dB
+    gain: f32,
+    #[serde(skip)]
+    generators: Vec<NoiseGenerator>,
+    #[serde(skip)]
+    pending: VecDeque<Tick>,
+}
+
+impl HasNodeIo for NoiseNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl NoiseNode {
+    pub fn new(color: NoiseColor) -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            color,
+            gain: -12.0,
+            generators: vec![],
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn color(&self) -> NoiseColor {
+        self.color
+    }
+
+    pub fn color_mut(&mut self) -> &mut NoiseColor {
+        &mut self.color
+    }
+
+    pub fn gain_mut(&mut self) -> &mut f32 {
+        &mut self.gain
+    }
+
+    pub fn generate(&mut self, metadata: &AudioMetadata, duration: usize) -> DataChunk {
+        let channels = *metadata.channels();
+        while self.generators.len() < channels {
+            let seed = 0x9e37_79b9u32.wrapping_mul(self.generators.len() as u32 + 1);
+            self.generators.push(NoiseGenerator::new(seed));
+        }
+        let gain = db_to_amplitude(self.gain);
+        let color = self.color;
+        let samples = self.generators[..channels]
+            .iter_mut()
+            .map(|g| (0..duration).map(|_| g.next(color) * gain).collect())
+            .collect();
+        DataChunk::Real(GenericDataChunk::new(
+            samples,
+            metadata.clone(),
+            duration,
+            None,
+        ))
+    }
+}
+
+impl NodeTrait for NoiseNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn add_input(&mut self) -> Result<&mut InputPort, Box<dyn std::error::Error>> {
+        Err(Box::new(PortAdditionError))
+    }
+    fn is_source(&self) -> bool {
+        true
+    }
+    fn tick(&mut self, ticks: &[Tick]) {
+        self.pending.extend(ticks.iter().cloned());
+    }
+    fn run_once(&mut self) {
+        while let Some(tick) = self.pending.pop_front() {
+            let chunk = self.generate(&tick.metadata, tick.duration);
+            for output in self.outputs().iter() {
+                let _ = output.try_send(chunk.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // power of the first difference relative to the signal, which falls as the spectrum tilts
+    fn roughness(color: NoiseColor) -> f32 {
+        let mut noise = NoiseNode::new(color);
+        *noise.gain_mut() = 0.0;
+        let samples = match noise.generate(&AudioMetadata::new(2, 44100), 44100) {
+            DataChunk::Real(chunk) => {
+                assert_ne!(chunk.samples(0)[..16], chunk.samples(1)[..16]);
+                chunk.samples(0).to_vec()
+            }
+            _ => panic!(),
+        };
+        let power = samples.iter().map(|s| s * s).sum::<f32>();
+        let peak = samples.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!(
+            power / samples.len() as f32 > 1e-3 && peak < 1.5,
+            "{:?}",
+            color
+        );
+        let difference = samples
+            .windows(2)
+            .map(|w| (w[1] - w[0]).powi(2))
+            .sum::<f32>();
+        difference / power
+    }
+
+    #[test]
+    fn colors_tilt_the_spectrum() {
+        let white = roughness(NoiseColor::White);
+        let pink = roughness(NoiseColor::Pink);
+        let brown = roughness(NoiseColor::Brown);
+        assert!((white - 2.0).abs() < 0.1, "{}", white);
+        assert!(pink < white * 0.7, "{}", pink);
+        assert!(brown < pink * 0.2, "{}", brown);
+    }
+}
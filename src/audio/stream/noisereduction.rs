@@ -0,0 +1,379 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::node::*;
+use getset::Getters;
+use rustfft::num_complex::Complex32;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// smoothing of the power spectrum tracked by minimum statistics
+const MIN_STATS_SMOOTHING: f32 = 0.85;
+// number of sub-windows the minimum search window is split into
+const MIN_STATS_SUBWINDOWS: usize = 8;
+// the minimum of a smoothed noise power lies below its mean
+const MIN_STATS_BIAS: f32 = 1.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum NoiseEstimation {
+    // a profile learned on demand from a noise-only passage
+    Learned,
+    // continuously tracked minimum of the smoothed power of each bin
+    MinimumStatistics,
+}
+
+impl Default for NoiseEstimation {
+    fn default() -> Self {
+        NoiseEstimation::Learned
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MinimumStatistics {
+    smoothed: Vec<f32>,
+    // minima of the finished sub-windows, oldest first
+    minima: VecDeque<Vec<f32>>,
+    current: Vec<f32>,
+    frames: usize,
+}
+
+impl MinimumStatistics {
+    fn new(power: &[f32]) -> Self {
+        Self {
+            smoothed: power.to_vec(),
+            minima: VecDeque::new(),
+            current: power.to_vec(),
+            frames: 0,
+        }
+    }
+
+    fn update(&mut self, power: &[f32], subwindow: usize) {
+        for (k, p) in power.iter().enumerate() {
+            self.smoothed[k] =
+                MIN_STATS_SMOOTHING * self.smoothed[k] + (1.0 - MIN_STATS_SMOOTHING) * p;
+            self.current[k] = self.current[k].min(self.smoothed[k]);
+        }
+        self.frames += 1;
+        if self.frames >= subwindow {
+            self.minima.push_back(self.current.clone());
+            if self.minima.len() > MIN_STATS_SUBWINDOWS {
+                self.minima.pop_front();
+            }
+            self.current = self.smoothed.clone();
+            self.frames = 0;
+        }
+    }
+
+    fn noise(&self) -> Vec<f32> {
+        (0..self.current.len())
+            .map(|k| {
+                self.minima
+                    .iter()
+                    .map(|m| m[k])
+                    .fold(self.current[k], f32::min)
+                    * MIN_STATS_BIAS
+            })
+            .collect()
+    }
+}
+
+// Attenuates stationary noise in windowed spectra by spectral subtraction.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct NoiseReductionNode {
+    io: NodeIo,
+    id: NodeId,
+    #[serde(default)]
+    estimation: NoiseEstimation,
+    // multiplies the noise power subtracted from each bin
+    over_subtraction: f32,
+    // lowest gain of a bin in dB
+    floor: f32,
+    // length of the minimum search in ms
+    window_ms: f32,
+    // noise power of each bin of each channel
+    #[serde(skip)]
+    profile: Option<Vec<Vec<f32>>>,
+    #[serde(skip)]
+    learning: bool,
+    #[serde(skip)]
+    learned_frames: usize,
+    #[serde(skip)]
+    statistics: Vec<MinimumStatistics>,
+}
+
+impl HasNodeIo for NoiseReductionNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl NoiseReductionNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            estimation: NoiseEstimation::default(),
+            over_subtraction: 2.0,
+            floor: -20.0,
+            window_ms: 1500.0,
+            profile: None,
+            learning: false,
+            learned_frames: 0,
+            statistics: vec![],
+        }
+    }
+
+    pub fn estimation(&self) -> NoiseEstimation {
+        self.estimation
+    }
+
+    pub fn estimation_mut(&mut self) -> &mut NoiseEstimation {
+        &mut self.estimation
+    }
+
+    pub fn over_subtraction_mut(&mut self) -> &mut f32 {
+        &mut self.over_subtraction
+    }
+
+    pub fn floor_mut(&mut self) -> &mut f32 {
+        &mut self.floor
+    }
+
+    pub fn window_ms_mut(&mut self) -> &mut f32 {
+        &mut self.window_ms
+    }
+
+    pub fn learning(&self) -> bool {
+        self.learning
+    }
+
+    // averages the incoming spectra into a new profile until `stop_learning`
+    pub fn start_learning(&mut self) {
+        self.profile = None;
+        self.learned_frames = 0;
+        self.learning = true;
+    }
+
+    pub fn stop_learning(&mut self) -> bool {
+        let learning = self.learning;
+        self.learning = false;
+        learning
+    }
+
+    pub fn discard_profile(&mut self) -> bool {
+        self.learning = false;
+        self.profile.take().is_some()
+    }
+
+    // the noise power of the first channel in use, if any
+    pub fn noise_profile(&self) -> Option<Vec<f32>> {
+        match self.estimation {
+            NoiseEstimation::Learned => self.profile.as_ref().map(|p| p[0].clone()),
+            NoiseEstimation::MinimumStatistics => self.statistics.first().map(|s| s.noise()),
+        }
+    }
+
+    fn learn(&mut self, powers: &[Vec<f32>]) {
+        let frames = self.learned_frames as f32;
+        match &mut self.profile {
+            Some(profile)
+                if profile.len() == powers.len() && profile[0].len() == powers[0].len() =>
+            {
+                for (profile, power) in profile.iter_mut().zip(powers.iter()) {
+                    for (n, p) in profile.iter_mut().zip(power.iter()) {
+                        *n = (*n * frames + p) / (frames + 1.0);
+                    }
+                }
+                self.learned_frames += 1;
+            }
+            _ => {
+                self.profile = Some(powers.to_vec());
+                self.learned_frames = 1;
+            }
+        }
+    }
+
+    fn track(&mut self, powers: &[Vec<f32>], subwindow: usize) {
+        let compatible = self.statistics.len() == powers.len()
+            && self.statistics[0].smoothed.len() == powers[0].len();
+        if !compatible {
+            self.statistics = powers.iter().map(|p| MinimumStatistics::new(p)).collect();
+        }
+        for (statistics, power) in self.statistics.iter_mut().zip(powers.iter()) {
+            statistics.update(power, subwindow);
+        }
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Complex(chunk) => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let hop = match chunk.window_info() {
+            Some(info) => (*info.delay()).max(1),
+            None => {
+                eprintln!("not windowed {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let powers = (0..channels)
+            .map(|c| chunk.samples(c).iter().map(|s| s.norm_sqr()).collect())
+            .collect::<Vec<Vec<f32>>>();
+
+        let noise = match self.estimation {
+            NoiseEstimation::Learned => {
+                if self.learning {
+                    self.learn(&powers);
+                }
+                match &self.profile {
+                    Some(profile)
+                        if profile.len() == channels && profile[0].len() == *chunk.duration() =>
+                    {
+                        profile.clone()
+                    }
+                    Some(_) => {
+                        eprintln!("incompatible noise profile {}: {}", file!(), line!());
+                        return Some(DataChunk::Complex(chunk));
+                    }
+                    None => return Some(DataChunk::Complex(chunk)),
+                }
+            }
+            NoiseEstimation::MinimumStatistics => {
+                let frames = self.window_ms * sample_rate as f32 / 1000.0 / hop as f32;
+                let subwindow = (frames as usize / MIN_STATS_SUBWINDOWS).max(1);
+                self.track(&powers, subwindow);
+                self.statistics.iter().map(|s| s.noise()).collect()
+            }
+        };
+
+        let floor = db_to_amplitude(self.floor);
+        let samples = (0..channels)
+            .map(|c| {
+                chunk
+                    .samples(c)
+                    .iter()
+                    .zip(powers[c].iter().zip(noise[c].iter()))
+                    .map(|(s, (p, n))| {
+                        let gain = if *p > 0.0 {
+                            (1.0 - self.over_subtraction * n / p).max(0.0).sqrt()
+                        } else {
+                            0.0
+                        };
+                        s * gain.max(floor)
+                    })
+                    .collect::<Vec<Complex32>>()
+            })
+            .collect();
+        Some(DataChunk::Complex(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            *chunk.duration(),
+            chunk.window_info().clone(),
+        )))
+    }
+}
+
+impl NodeTrait for NoiseReductionNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testutil::*;
+    use super::*;
+
+    const N: usize = 64;
+    const TONE_BIN: usize = 5;
+
+    // unit noise magnitude with pseudo-random phases and magnitude jitter, plus an optional tone
+    fn frame(seed: &mut u32, tone: f32) -> DataChunk {
+        let mut spectrum = (0..N)
+            .map(|_| {
+                let r = random(seed);
+                Complex32::from_polar(&(0.75 + 0.5 * r), &(2.0 * std::f32::consts::PI * r))
+            })
+            .collect::<Vec<_>>();
+        spectrum[TONE_BIN] += tone;
+        spectrum[N - TONE_BIN] += tone;
+        DataChunk::Complex(GenericDataChunk::new(
+            vec![spectrum],
+            AudioMetadata::new(1, 8000),
+            N,
+            Some(WindowInfo::new(WindowFunction::Hanning, N / 4)),
+        ))
+    }
+
+    fn magnitudes(chunk: Option<DataChunk>) -> Vec<f32> {
+        match chunk {
+            Some(DataChunk::Complex(chunk)) => chunk.samples(0).iter().map(|s| s.norm()).collect(),
+            _ => panic!(),
+        }
+    }
+
+    fn check(node: &mut NoiseReductionNode, seed: &mut u32) {
+        let out = magnitudes(node.process_chunk(frame(seed, 20.0)));
+        assert!(out[TONE_BIN] > 15.0, "{}", out[TONE_BIN]);
+        let noise = out
+            .iter()
+            .enumerate()
+            .filter(|(k, _)| *k != TONE_BIN && *k != N - TONE_BIN)
+            .map(|(_, m)| m)
+            .sum::<f32>()
+            / (N - 2) as f32;
+        assert!(noise < 0.2, "{}", noise);
+    }
+
+    #[test]
+    fn learned_profile_removes_noise() {
+        let mut seed = 1;
+        let mut node = NoiseReductionNode::new();
+        // nothing is removed before a profile is learned
+        let out = magnitudes(node.process_chunk(frame(&mut seed, 0.0)));
+        assert!(out[1] > 0.7);
+        node.start_learning();
+        for _ in 0..50 {
+            node.process_chunk(frame(&mut seed, 0.0));
+        }
+        assert!(node.stop_learning());
+        let profile = node.noise_profile().unwrap();
+        assert!((profile[1] - 1.0).abs() < 0.3, "{}", profile[1]);
+        check(&mut node, &mut seed);
+    }
+
+    #[test]
+    fn minimum_statistics_tracks_noise() {
+        let mut seed = 1;
+        let mut node = NoiseReductionNode::new();
+        *node.estimation_mut() = NoiseEstimation::MinimumStatistics;
+        // the tone is present most of the time; the pauses reveal the noise floor
+        for i in 0..400 {
+            let tone = if i % 50 < 35 { 20.0 } else { 0.0 };
+            node.process_chunk(frame(&mut seed, tone));
+        }
+        check(&mut node, &mut seed);
+    }
+}
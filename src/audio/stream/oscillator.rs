@@ -0,0 +1,278 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+    Pulse,
+}
+
+// polynomial band-limited step, subtracted around a jump at phase 0
+fn blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+// polynomial band-limited ramp, the integral of `blep`, for a corner at phase 0
+fn blamp(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -x * x * x / 3.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 3.0
+    } else {
+        0.0
+    }
+}
+
+// one sample at `phase` in [0, 1), advancing by `dt` per sample; 0 disables band-limiting
+pub fn waveform_sample(waveform: Waveform, phase: f64, dt: f64, pulse_width: f64) -> f32 {
+    let shifted = |offset: f64| (phase + offset).fract();
+    let value = match waveform {
+        Waveform::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
+        Waveform::Saw => 2.0 * phase - 1.0 - blep(phase, dt),
+        Waveform::Square => {
+            let naive = if phase < 0.5 { 1.0 } else { -1.0 };
+            naive + blep(phase, dt) - blep(shifted(0.5), dt)
+        }
+        Waveform::Triangle => {
+            let naive = 1.0 - 4.0 * (phase - 0.5).abs();
+            // the slope changes by 8 per period at both corners, `blamp` covers 2
+            naive + 4.0 * dt * (blamp(phase, dt) - blamp(shifted(0.5), dt))
+        }
+        Waveform::Pulse => {
+            let naive = if phase < pulse_width { 1.0 } else { -1.0 };
+            // without its DC offset
+            naive + blep(phase, dt)
+                - blep(shifted(1.0 - pulse_width), dt)
+                - (2.0 * pulse_width - 1.0)
+        }
+    };
+    value as f32
+}
+
+// Produces a periodic waveform in step with the chunks entering the graph.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct OscillatorNode {
+    io: NodeIo,
+    id: NodeId,
+    waveform: Waveform,
+    // Hz
+    frequency: f32,
+    // dB
+    gain: f32,
+    // fraction of the period spent high, only for pulses
+    pulse_width: f32,
+    #[serde(skip)]
+    phase: f64,
+    #[serde(skip)]
+    pending: VecDeque<Tick>,
+}
+
+impl HasNodeIo for OscillatorNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl OscillatorNode {
+    pub fn new(waveform: Waveform) -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            waveform,
+            frequency: 220.0,
+            gain: -12.0,
+            pulse_width: 0.25,
+            phase: 0.0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    pub fn waveform_mut(&mut self) -> &mut Waveform {
+        &mut self.waveform
+    }
+
+    pub fn frequency_mut(&mut self) -> &mut f32 {
+        &mut self.frequency
+    }
+
+    pub fn gain_mut(&mut self) -> &mut f32 {
+        &mut self.gain
+    }
+
+    pub fn pulse_width_mut(&mut self) -> &mut f32 {
+        &mut self.pulse_width
+    }
+
+    // one period without band-limiting
+    pub fn preview(&self, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| {
+                waveform_sample(
+                    self.waveform,
+                    i as f64 / length as f64,
+                    0.0,
+                    self.pulse_width as f64,
+                )
+            })
+            .collect()
+    }
+
+    pub fn generate(&mut self, metadata: &AudioMetadata, duration: usize) -> DataChunk {
+        let sample_rate = *metadata.sample_rate() as f64;
+        let dt = (self.frequency as f64 / sample_rate).max(0.0).min(0.5);
+        let pulse_width = (self.pulse_width as f64).max(0.01).min(0.99);
+        let gain = db_to_amplitude(self.gain);
+        let samples = (0..duration)
+            .map(|_| {
+                let value = waveform_sample(self.waveform, self.phase, dt, pulse_width) * gain;
+                self.phase = (self.phase + dt).fract();
+                value
+            })
+            .collect::<Vec<_>>();
+        DataChunk::Real(GenericDataChunk::new(
+            vec![samples; *metadata.channels()],
+            metadata.clone(),
+            duration,
+            None,
+        ))
+    }
+}
+
+impl NodeTrait for OscillatorNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn add_input(&mut self) -> Result<&mut InputPort, Box<dyn std::error::Error>> {
+        Err(Box::new(PortAdditionError))
+    }
+    fn is_source(&self) -> bool {
+        true
+    }
+    fn tick(&mut self, ticks: &[Tick]) {
+        self.pending.extend(ticks.iter().cloned());
+    }
+    fn run_once(&mut self) {
+        while let Some(tick) = self.pending.pop_front() {
+            let chunk = self.generate(&tick.metadata, tick.duration);
+            for output in self.outputs().iter() {
+                let _ = output.try_send(chunk.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 16000;
+
+    const LENGTH: usize = 1024;
+
+    fn render(waveform: Waveform, frequency: f32, band_limited: bool) -> Vec<f32> {
+        let mut oscillator = OscillatorNode::new(waveform);
+        *oscillator.frequency_mut() = frequency;
+        *oscillator.gain_mut() = 0.0;
+        let metadata = AudioMetadata::new(1, SAMPLE_RATE);
+        if band_limited {
+            let mut samples = vec![];
+            // in chunks, to check that the phase carries over
+            for _ in 0..4 {
+                match oscillator.generate(&metadata, LENGTH / 4) {
+                    DataChunk::Real(chunk) => samples.extend_from_slice(chunk.samples(0)),
+                    _ => panic!(),
+                }
+            }
+            samples
+        } else {
+            let dt = frequency as f64 / SAMPLE_RATE as f64;
+            (0..LENGTH)
+                .map(|i| waveform_sample(waveform, (i as f64 * dt).fract(), 0.0, 0.25))
+                .collect()
+        }
+    }
+
+    // energy outside the harmonics relative to the energy in them, for `periods` periods
+    fn aliasing(samples: &[f32], periods: usize) -> f32 {
+        let (mut harmonic, mut aliased) = (0.0, 0.0);
+        for bin in 1..LENGTH / 2 {
+            let (re, im) = samples
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (i, s)| {
+                    let phase =
+                        2.0 * std::f32::consts::PI * (bin * i % LENGTH) as f32 / LENGTH as f32;
+                    (re + s * phase.cos(), im + s * phase.sin())
+                });
+            if bin % periods == 0 {
+                harmonic += re * re + im * im;
+            } else {
+                aliased += re * re + im * im;
+            }
+        }
+        aliased / harmonic
+    }
+
+    #[test]
+    fn band_limiting_reduces_aliasing() {
+        // a whole number of periods in the buffer keeps the harmonics on their own bins
+        let periods = 79;
+        let frequency = (SAMPLE_RATE * periods) as f32 / LENGTH as f32;
+        for waveform in [
+            Waveform::Saw,
+            Waveform::Square,
+            Waveform::Triangle,
+            Waveform::Pulse,
+        ]
+        .iter()
+        {
+            let band_limited = aliasing(&render(*waveform, frequency, true), periods);
+            let naive = aliasing(&render(*waveform, frequency, false), periods);
+            assert!(
+                band_limited < naive * 0.1,
+                "{:?} {} {}",
+                waveform,
+                band_limited,
+                naive
+            );
+        }
+    }
+
+    #[test]
+    fn sine_has_its_frequency() {
+        let samples = render(Waveform::Sine, 500.0, true);
+        let crossings = samples
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        // 1024 samples at 16 kHz hold 32 periods
+        assert!((crossings as isize - 32).abs() <= 1, "{}", crossings);
+    }
+}
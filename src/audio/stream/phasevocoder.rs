@@ -11,13 +11,32 @@ pub enum PitchShiftConfig {
     Kumaraswamy(f32, f32),
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PhaseLocking {
+    Off,
+    // bins around a peak keep their analysis phase relative to the peak
+    Identity,
+    // the relative phases are scaled by the local pitch shift rate
+    Scaled,
+}
+
+impl Default for PhaseLocking {
+    fn default() -> Self {
+        PhaseLocking::Identity
+    }
+}
+
 #[derive(Getters, Serialize, Deserialize, Debug)]
 pub struct PhaseVocoder {
     io: NodeIo,
     id: NodeId,
     config: PitchShiftConfig,
+    #[serde(default)]
+    phase_locking: PhaseLocking,
+    #[serde(skip)]
+    prev_phases: Vec<Vec<f32>>,
     #[serde(skip)]
-    prev_unwrapped_phases: Vec<Vec<f32>>,
+    prev_out_phases: Vec<Vec<f32>>,
 }
 
 impl HasNodeIo for PhaseVocoder {
@@ -29,13 +48,20 @@ impl HasNodeIo for PhaseVocoder {
     }
 }
 
+fn princarg(phase: f32) -> f32 {
+    let two_pi = 2.0 * std::f32::consts::PI;
+    phase - two_pi * (phase / two_pi).round()
+}
+
 impl PhaseVocoder {
     pub fn new(rate: f32) -> Self {
         Self {
             io: NodeIo::new(),
             id: NodeId::new(),
-            config: PitchShiftConfig::Rate(1.0),
-            prev_unwrapped_phases: vec![],
+            config: PitchShiftConfig::Rate(rate),
+            phase_locking: PhaseLocking::default(),
+            prev_phases: vec![],
+            prev_out_phases: vec![],
         }
     }
 
@@ -46,125 +72,157 @@ impl PhaseVocoder {
         &mut self.config
     }
 
-    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
-        let channels = *chunk.metadata().channels();
-        while self.prev_unwrapped_phases.len() < channels {
-            self.prev_unwrapped_phases.push(vec![]);
-        }
-        for p in self.prev_unwrapped_phases.iter_mut() {
-            while p.len() < *chunk.duration() {
-                p.push(0.0);
+    pub fn phase_locking(&self) -> PhaseLocking {
+        self.phase_locking
+    }
+    pub fn phase_locking_mut(&mut self) -> &mut PhaseLocking {
+        &mut self.phase_locking
+    }
+
+    // the (fractional) bin which bin `k` of `half` positive bins is moved to
+    fn warp(&self, k: usize, half: usize) -> f32 {
+        match self.config {
+            PitchShiftConfig::Rate(rate) => k as f32 * rate,
+            PitchShiftConfig::Kumaraswamy(a, b) => {
+                let x = k as f32 / half as f32;
+                (1.0 - (1.0 - x.powf(a)).powf(b)) * half as f32
             }
         }
+    }
 
-        let mut incompatible = false;
-        let samples = (0..channels)
-            .map(|c| match &chunk {
-                DataChunk::Real(_) => {
-                    eprintln!("incompatible input {}: {}", file!(), line!());
-                    incompatible = true;
-                    vec![]
-                }
-                DataChunk::Complex(chunk) => chunk.samples(c).to_vec(),
+    fn shift_frame(
+        &self,
+        spectrum: &[Complex32],
+        hop: usize,
+        prev_phases: &mut Vec<f32>,
+        prev_out_phases: &mut Vec<f32>,
+    ) -> Vec<Complex32> {
+        let n = spectrum.len();
+        let half = n / 2;
+        let pi = std::f32::consts::PI;
+        if prev_phases.len() != half + 1 {
+            *prev_phases = vec![0.0; half + 1];
+            *prev_out_phases = vec![0.0; half + 1];
+        }
+
+        // instantaneous frequency (radians per sample) of each analysis bin
+        let phases = spectrum[..=half]
+            .iter()
+            .map(|s| s.arg())
+            .collect::<Vec<_>>();
+        let frequencies = (0..=half)
+            .map(|k| {
+                let bin_frequency = 2.0 * pi * k as f32 / n as f32;
+                let deviation = princarg(phases[k] - prev_phases[k] - bin_frequency * hop as f32);
+                bin_frequency + deviation / hop as f32
             })
             .collect::<Vec<_>>();
-        if incompatible {
-            return None;
-        }
-        let mut unwrapped_phases = vec![vec![]; channels];
-        for c in 0..channels {
-            let duration = samples[c].len();
-            for i in 0..duration {
-                let mut prev_unwrapped_phase = self.prev_unwrapped_phases[c][i];
-                if prev_unwrapped_phase.is_nan() {
-                    prev_unwrapped_phase = 0.0;
-                }
-                let pi = 3.141592;
-                let phase = samples[c][i].arg() % (2.0 * pi);
-                let prev_phase = prev_unwrapped_phase % (2.0 * pi);
-                let unwrapped_phase = prev_unwrapped_phase + phase - prev_phase
-                    + if phase - prev_phase < -pi {
-                        2.0 * pi
-                    } else if phase - prev_phase > pi {
-                        -2.0 * pi
-                    } else {
-                        0.0
-                    };
-                unwrapped_phases[c].push(unwrapped_phase);
+
+        // move the bins, keeping the strongest source of each target bin
+        let mut magnitudes = vec![0.0; half + 1];
+        let mut sources: Vec<Option<(usize, f32)>> = vec![None; half + 1];
+        for k in 0..=half {
+            let target = self.warp(k, half);
+            let t = target.round() as usize;
+            if t > half {
+                continue;
+            }
+            let magnitude = spectrum[k].norm();
+            magnitudes[t] += magnitude;
+            let rate = if k == 0 { 1.0 } else { target / k as f32 };
+            match sources[t] {
+                Some((s, _)) if spectrum[s].norm() >= magnitude => {}
+                _ => sources[t] = Some((k, rate)),
             }
         }
 
-        let mut scaled = vec![vec![]; channels];
-        for c in 0..channels {
-            let duration = samples[c].len();
-            match self.config {
-                PitchShiftConfig::Rate(rate) => {
-                    for i in 0..duration / 2 {
-                        let unscaled_index = (i as f32 / rate).ceil() as usize;
-                        if unscaled_index < duration / 2 {
-                            scaled[c].push(Complex32::from_polar(
-                                &(samples[c][unscaled_index].norm() / rate),
-                                &(rate * unwrapped_phases[c][unscaled_index]),
-                            ));
-                        } else {
-                            scaled[c].push(Complex32::zero());
-                        }
-                    }
-                    for i in duration / 2..duration {
-                        let unscaled_index =
-                            duration - ((duration - i - 1) as f32 / rate).ceil() as usize - 1;
-                        if unscaled_index >= duration / 2 {
-                            scaled[c].push(Complex32::from_polar(
-                                &(samples[c][unscaled_index].norm() / rate),
-                                &(rate * unwrapped_phases[c][unscaled_index]),
-                            ));
-                        } else {
-                            scaled[c].push(Complex32::zero());
-                        }
-                    }
+        let propagate = |t: usize| match sources[t] {
+            Some((k, rate)) => prev_out_phases[t] + frequencies[k] * rate * hop as f32,
+            None => prev_out_phases[t] + 2.0 * pi * t as f32 / n as f32 * hop as f32,
+        };
+        let mut out_phases = (0..=half).map(propagate).collect::<Vec<_>>();
+
+        if self.phase_locking != PhaseLocking::Off {
+            let is_peak = |t: usize| {
+                let m = magnitudes[t];
+                m > 0.0
+                    && (t.saturating_sub(2)..=(t + 2).min(half))
+                        .all(|u| u == t || magnitudes[u] < m)
+            };
+            let peaks = (0..=half).filter(|t| is_peak(*t)).collect::<Vec<_>>();
+            // each bin follows the nearest peak
+            let mut p = 0;
+            for t in 0..=half {
+                if peaks.is_empty() {
+                    break;
+                }
+                while p + 1 < peaks.len()
+                    && peaks[p + 1] as isize - t as isize <= t as isize - peaks[p] as isize
+                {
+                    p += 1;
                 }
-                PitchShiftConfig::Kumaraswamy(a, b) => {
-                    let kumaraswamy_inv = |u: f32| 1.0 - (1.0 - u.pow(1.0 / a)).pow(1.0 / b);
-                    for i in 0..duration / 2 {
-                        let unscaled_index = (kumaraswamy_inv(i as f32 / (duration / 2) as f32)
-                            * (duration / 2) as f32)
-                            .ceil() as usize;
-                        let rate = i as f32 / unscaled_index as f32;
-                        let rate = if rate.is_nan() { 1.0 } else { rate };
-                        if unscaled_index < duration / 2 {
-                            scaled[c].push(Complex32::from_polar(
-                                &(samples[c][unscaled_index].norm() / rate),
-                                &(rate * unwrapped_phases[c][unscaled_index]),
-                            ));
-                        } else {
-                            scaled[c].push(Complex32::zero());
-                        }
-                    }
-                    for i in duration / 2..duration {
-                        let unscaled_index = duration
-                            - (kumaraswamy_inv((duration - i - 1) as f32 / (duration / 2) as f32)
-                                * (duration / 2) as f32)
-                                .ceil() as usize
-                            - 1;
-                        let rate = (duration - i) as f32 / (duration - unscaled_index) as f32;
-                        let rate = if rate.is_nan() { 1.0 } else { rate };
-                        if unscaled_index >= duration / 2 {
-                            scaled[c].push(Complex32::from_polar(
-                                &(samples[c][unscaled_index].norm() / rate),
-                                &(rate * unwrapped_phases[c][unscaled_index]),
-                            ));
-                        } else {
-                            scaled[c].push(Complex32::zero());
-                        }
-                    }
+                let peak = peaks[p];
+                if t == peak {
+                    continue;
+                }
+                if let (Some((k, _)), Some((k_peak, rate))) = (sources[t], sources[peak]) {
+                    let beta = match self.phase_locking {
+                        PhaseLocking::Scaled => rate,
+                        _ => 1.0,
+                    };
+                    out_phases[t] = out_phases[peak] + beta * (phases[k] - phases[k_peak]);
                 }
             }
         }
 
-        self.prev_unwrapped_phases = unwrapped_phases;
+        let mut shifted = vec![Complex32::zero(); n];
+        for t in 0..=half {
+            shifted[t] = Complex32::from_polar(&magnitudes[t], &out_phases[t]);
+        }
+        // a real signal has a conjugate symmetric spectrum
+        for t in half + 1..n {
+            shifted[t] = shifted[n - t].conj();
+        }
+        *prev_phases = phases;
+        *prev_out_phases = out_phases.iter().map(|p| princarg(*p)).collect();
+        shifted
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Complex(chunk) => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let hop = match chunk.window_info() {
+            Some(info) => *info.delay(),
+            None => {
+                eprintln!("not windowed {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        self.prev_phases.resize(channels, vec![]);
+        self.prev_out_phases.resize(channels, vec![]);
+        let mut prev_phases = std::mem::replace(&mut self.prev_phases, vec![]);
+        let mut prev_out_phases = std::mem::replace(&mut self.prev_out_phases, vec![]);
+        let shifted = (0..channels)
+            .map(|c| {
+                self.shift_frame(
+                    chunk.samples(c),
+                    hop.max(1),
+                    &mut prev_phases[c],
+                    &mut prev_out_phases[c],
+                )
+            })
+            .collect::<Vec<_>>();
+        self.prev_phases = prev_phases;
+        self.prev_out_phases = prev_out_phases;
 
         let new_chunk = GenericDataChunk::new(
-            scaled,
+            shifted,
             chunk.metadata().clone(),
             chunk.duration().clone(),
             chunk.window_info().clone(),
@@ -177,6 +235,9 @@ impl NodeTrait for PhaseVocoder {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
@@ -190,3 +251,88 @@ impl NodeTrait for PhaseVocoder {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn windowed_spectrum(
+        frequency: f32,
+        sample_rate: usize,
+        offset: usize,
+        n: usize,
+    ) -> Vec<Complex32> {
+        let pi = std::f32::consts::PI;
+        let frame = (0..n)
+            .map(|i| {
+                let t = (offset + i) as f32 / sample_rate as f32;
+                let window = 0.5 - 0.5 * (2.0 * pi * i as f32 / n as f32).cos();
+                (2.0 * pi * frequency * t).sin() * window
+            })
+            .collect::<Vec<_>>();
+        (0..n)
+            .map(|k| {
+                frame
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| {
+                        Complex32::from_polar(s, &(-2.0 * pi * (k * i) as f32 / n as f32))
+                    })
+                    .fold(Complex32::zero(), |a, b| a + b)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn phase_advances_at_shifted_frequency() {
+        let (sample_rate, n, hop) = (8000, 256, 64);
+        let frequency = 440.0;
+        let rate = 1.5;
+        for locking in [
+            PhaseLocking::Off,
+            PhaseLocking::Identity,
+            PhaseLocking::Scaled,
+        ]
+        .iter()
+        {
+            let mut pv = PhaseVocoder::new(rate);
+            *pv.phase_locking_mut() = *locking;
+            let mut frames = vec![];
+            for j in 0..6 {
+                let spectrum = windowed_spectrum(frequency, sample_rate, j * hop, n);
+                let chunk = DataChunk::Complex(GenericDataChunk::new(
+                    vec![spectrum],
+                    AudioMetadata::new(1, sample_rate),
+                    n,
+                    Some(WindowInfo::new(WindowFunction::Hanning, hop)),
+                ));
+                match pv.process_chunk(chunk) {
+                    Some(DataChunk::Complex(c)) => frames.push(c.samples(0).to_vec()),
+                    _ => panic!(),
+                }
+            }
+            let (a, b) = (&frames[4], &frames[5]);
+            let peak = (0..n / 2).fold(0, |p, k| if a[k].norm() > a[p].norm() { k } else { p });
+            let expected_bin = frequency * rate * n as f32 / sample_rate as f32;
+            assert!(
+                (peak as f32 - expected_bin).abs() <= 1.0,
+                "{:?} {}",
+                locking,
+                peak
+            );
+            let expected = princarg(
+                2.0 * std::f32::consts::PI * frequency * rate * hop as f32 / sample_rate as f32,
+            );
+            let advance = princarg(b[peak].arg() - a[peak].arg());
+            assert!(
+                (advance - expected).abs() < 0.05,
+                "{:?} {} {}",
+                locking,
+                advance,
+                expected
+            );
+            // the output stays conjugate symmetric
+            assert!((a[3] - a[n - 3].conj()).norm() < 1e-4);
+        }
+    }
+}
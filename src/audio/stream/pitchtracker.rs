@@ -0,0 +1,236 @@
+use super::super::common::*;
+use super::super::pitch::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+const HISTORY_LENGTH: usize = 256;
+
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct PitchTrackerNode {
+    io: NodeIo,
+    id: NodeId,
+    min_frequency: f32,
+    max_frequency: f32,
+    #[serde(skip)]
+    trackers: Vec<ProbabilisticYin>,
+    // the latest input of each channel, long enough for the lowest pitch whatever the chunk size
+    #[serde(skip)]
+    inputs: Vec<Vec<f32>>,
+    // f0 (0 when unvoiced) and confidence of the first channel, oldest first
+    #[serde(skip)]
+    frequency_history: Vec<f32>,
+    #[serde(skip)]
+    confidence_history: Vec<f32>,
+}
+
+impl HasNodeIo for PitchTrackerNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl PitchTrackerNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            min_frequency: DEFAULT_PITCH_RANGE.0,
+            max_frequency: DEFAULT_PITCH_RANGE.1,
+            trackers: vec![],
+            inputs: vec![],
+            frequency_history: vec![],
+            confidence_history: vec![],
+        }
+    }
+
+    pub fn min_frequency(&self) -> f32 {
+        self.min_frequency
+    }
+
+    pub fn min_frequency_mut(&mut self) -> &mut f32 {
+        &mut self.min_frequency
+    }
+
+    pub fn max_frequency(&self) -> f32 {
+        self.max_frequency
+    }
+
+    pub fn max_frequency_mut(&mut self) -> &mut f32 {
+        &mut self.max_frequency
+    }
+
+    pub fn frequency_history(&self) -> &[f32] {
+        &self.frequency_history
+    }
+
+    pub fn confidence_history(&self) -> &[f32] {
+        &self.confidence_history
+    }
+
+    fn pitch_range(&self) -> (f32, f32) {
+        let min = self.min_frequency.max(1.0);
+        (min, self.max_frequency.max(min * 2.0))
+    }
+
+    fn record(&mut self, frequency: Option<f32>, confidence: f32) {
+        self.frequency_history.push(frequency.unwrap_or(0.0));
+        self.confidence_history.push(confidence);
+        if self.frequency_history.len() > HISTORY_LENGTH {
+            self.frequency_history.remove(0);
+            self.confidence_history.remove(0);
+        }
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let range = self.pitch_range();
+        while self.trackers.len() < channels {
+            self.trackers.push(ProbabilisticYin::new(range));
+            self.inputs.push(vec![]);
+        }
+        // two of the longest periods, as the lag can be at most half of the data
+        let analysis_len =
+            (2 * (sample_rate as f32 / range.0).ceil() as usize).max(*chunk.duration());
+        let estimates = (0..channels)
+            .map(|c| {
+                let input = &mut self.inputs[c];
+                input.extend_from_slice(chunk.samples(c));
+                let excess = input.len().saturating_sub(analysis_len);
+                input.drain(..excess);
+                let tracker = &mut self.trackers[c];
+                tracker.set_range(range);
+                tracker.estimate(input, sample_rate)
+            })
+            .collect::<Vec<_>>();
+        if let Some(estimate) = estimates.first() {
+            self.record(
+                estimate.map(|p| p.frequency),
+                estimate.map(|p| p.confidence).unwrap_or(0.0),
+            );
+        }
+        let values = estimates
+            .iter()
+            .map(|estimate| ControlValue::Pitch {
+                frequency: estimate.map(|p| p.frequency),
+                confidence: estimate.map(|p| p.confidence).unwrap_or(0.0),
+            })
+            .collect();
+        Some(DataChunk::Control(ControlChunk::new(
+            values,
+            chunk.metadata().clone(),
+            *chunk.duration(),
+        )))
+    }
+}
+
+impl NodeTrait for PitchTrackerNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn is_control_output(&self, _index: usize) -> bool {
+        true
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44100;
+
+    fn sine(frequency: f32, offset: usize, len: usize) -> Vec<f32> {
+        (offset..offset + len)
+            .map(|i| {
+                0.5 * (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    fn pitch(node: &mut PitchTrackerNode, samples: Vec<f32>) -> (Option<f32>, f32) {
+        let len = samples.len();
+        let chunk = GenericDataChunk::new(
+            vec![samples.clone(), samples],
+            AudioMetadata::new(2, SAMPLE_RATE),
+            len,
+            None,
+        );
+        match node.process_chunk(DataChunk::Real(chunk)) {
+            Some(DataChunk::Control(chunk)) => {
+                assert_eq!(*chunk.duration(), len);
+                assert_eq!(chunk.value(0), chunk.value(1));
+                match chunk.value(0) {
+                    ControlValue::Pitch {
+                        frequency,
+                        confidence,
+                    } => (*frequency, *confidence),
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn emits_pitch_of_voiced_input_only() {
+        let mut node = PitchTrackerNode::new();
+        for k in 0..4 {
+            let (frequency, confidence) = pitch(&mut node, sine(220.0, k * 2048, 2048));
+            let frequency = frequency.unwrap();
+            assert!((frequency - 220.0).abs() < 2.0, "{}", frequency);
+            assert!(confidence > 0.5, "{}", confidence);
+        }
+        assert_eq!(node.frequency_history().len(), 4);
+
+        let (frequency, confidence) = pitch(&mut node, vec![0.0; 2048]);
+        assert_eq!(frequency, None);
+        assert_eq!(confidence, 0.0);
+        assert_eq!(node.frequency_history().last(), Some(&0.0));
+    }
+
+    #[test]
+    fn tracks_low_pitch_in_short_chunks() {
+        // a period of 80 Hz is longer than half of the chunks
+        let mut node = PitchTrackerNode::new();
+        for k in 0..8 {
+            let (frequency, _) = pitch(&mut node, sine(80.0, k * 1024, 1024));
+            if k >= 2 {
+                let frequency = frequency.unwrap();
+                assert!((frequency - 80.0).abs() < 1.0, "{}", frequency);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_control_input() {
+        let mut node = PitchTrackerNode::new();
+        let chunk = ControlChunk::new(vec![], AudioMetadata::new(1, SAMPLE_RATE), 2048);
+        assert!(node.process_chunk(DataChunk::Control(chunk)).is_none());
+    }
+}
@@ -1,26 +1,339 @@
 use super::super::common::*;
+use super::super::pitch::*;
 use super::node::*;
 use getset::Getters;
-use rustfft::num_complex::Complex32;
-use rustfft::FFTplanner;
 use serde::{Deserialize, Serialize};
 
+// Input history of one channel. Positions are absolute sample indices of the stream.
+#[derive(Clone, Debug, Default)]
+struct PsolaInput {
+    samples: Vec<f32>,
+    start: isize,
+}
+
+impl PsolaInput {
+    fn end(&self) -> isize {
+        self.start + self.samples.len() as isize
+    }
+
+    fn at(&self, t: isize) -> f32 {
+        let i = t - self.start;
+        if i >= 0 && i < self.samples.len() as isize {
+            self.samples[i as usize]
+        } else {
+            0.0
+        }
+    }
+
+    // the part of [start, end) which is still buffered
+    fn range(&self, start: isize, end: isize) -> &[f32] {
+        let len = self.samples.len() as isize;
+        let start = (start - self.start).max(0).min(len) as usize;
+        let end = (end - self.start).max(0).min(len) as usize;
+        &self.samples[start..end.max(start)]
+    }
+
+    fn forget(&mut self, before: isize) {
+        let n = (before - self.start)
+            .max(0)
+            .min(self.samples.len() as isize) as usize;
+        self.samples.drain(..n);
+        self.start += n as isize;
+    }
+}
+
+// Pitch marks and output buffer of one pitch-shifted voice
 #[derive(Clone, Debug)]
-struct PsolaInfo {
-    last_in_center: isize,
-    last_out_center: isize,
-    in_period: isize,
+struct PsolaVoice {
+    output: Vec<f32>,
+    output_start: isize,
+    in_center: isize,
+    out_center: isize,
+    period: usize,
+}
+
+impl PsolaVoice {
+    fn new(period: usize, position: isize) -> Self {
+        Self {
+            output: vec![],
+            output_start: position,
+            in_center: position,
+            out_center: position,
+            period: period.max(1),
+        }
+    }
+
+    fn add_grain(
+        &mut self,
+        input: &PsolaInput,
+        window_function: WindowFunction,
+        in_center: isize,
+        out_center: isize,
+        period: usize,
+    ) {
+        let p = period as isize;
+        for d in -p..p {
+            let k = out_center + d - self.output_start;
+            if k < 0 {
+                continue;
+            }
+            let k = k as usize;
+            if k >= self.output.len() {
+                self.output.resize(k + 1, 0.0);
+            }
+            self.output[k] += input.at(in_center + d)
+                * PsolaNode::window(window_function, (d + p) as usize, period * 2);
+        }
+    }
+
+    // places every grain centered before `end`
+    fn synthesize(
+        &mut self,
+        input: &PsolaInput,
+        end: isize,
+        period: Option<usize>,
+        ratio: f32,
+        window_function: WindowFunction,
+    ) {
+        while self.out_center < end {
+            match period {
+                Some(period) => {
+                    // pitch marks continue from the previous chunk
+                    let in_period = period as isize;
+                    while (self.in_center + in_period - self.out_center).abs()
+                        < (self.in_center - self.out_center).abs()
+                        && self.in_center + in_period * 2 <= input.end()
+                    {
+                        self.in_center += in_period;
+                    }
+                    let (in_center, out_center) = (self.in_center, self.out_center);
+                    self.add_grain(input, window_function, in_center, out_center, period);
+                    self.out_center += ((in_period as f32 / ratio).round() as isize).max(1);
+                    self.period = period;
+                }
+                None => {
+                    // unvoiced input is resynthesized as is
+                    self.in_center = self.out_center;
+                    let (center, period) = (self.out_center, self.period);
+                    self.add_grain(input, window_function, center, center, period);
+                    self.out_center += period as isize;
+                }
+            }
+        }
+    }
+
+    // takes the finished output in [end - len, end)
+    fn emit(&mut self, end: isize, len: usize) -> Vec<f32> {
+        let samples = (end - len as isize..end)
+            .map(|t| t - self.output_start)
+            .map(|k| {
+                if k >= 0 && k < self.output.len() as isize {
+                    self.output[k as usize]
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        if end > self.output_start {
+            let n = (end - self.output_start).min(self.output.len() as isize) as usize;
+            self.output.drain(..n);
+            self.output_start = end;
+        }
+        samples
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PsolaOutput {
+    // the input delayed by the latency of the engine, per channel
+    pub dry: Vec<Vec<f32>>,
+    // per channel and voice
+    pub voices: Vec<Vec<Vec<f32>>>,
+}
+
+// Streaming TD-PSOLA shifting the channels of a stream into any number of voices. The
+// output lags `delay` samples behind the input so that grains spanning chunk boundaries
+// are cut from real samples.
+#[derive(Debug, Default)]
+pub struct PsolaEngine {
+    inputs: Vec<PsolaInput>,
+    voices: Vec<Vec<PsolaVoice>>,
+    trackers: Vec<ProbabilisticYin>,
+    delay: usize,
+}
+
+impl PsolaEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    // voices are otherwise only added or dropped at the end, which would hand the state of
+    // each voice after `voice` to the one before it
+    pub fn remove_voice(&mut self, voice: usize) {
+        for voices in self.voices.iter_mut() {
+            if voice < voices.len() {
+                voices.remove(voice);
+            }
+        }
+    }
+
+    // `ratio` maps the channel, voice and pitch estimate of the chunk to a pitch ratio
+    pub fn process<F>(
+        &mut self,
+        chunk: &GenericDataChunk<f32>,
+        voices: usize,
+        range: (f32, f32),
+        voicing_threshold: f32,
+        window_function: WindowFunction,
+        mut ratio: F,
+    ) -> PsolaOutput
+    where
+        F: FnMut(usize, usize, Option<PitchEstimate>) -> f32,
+    {
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let duration = *chunk.duration();
+        let max_period = (sample_rate as f32 / range.0).ceil() as usize;
+        // enough for the grain placed at the latest output position plus the distance to its pitch mark
+        let delay = max_period * 3;
+        if self.delay != delay {
+            self.delay = delay;
+            self.inputs.clear();
+            self.voices.clear();
+            self.trackers.clear();
+        }
+        while self.inputs.len() < channels {
+            self.inputs.push(PsolaInput::default());
+            self.voices.push(vec![]);
+            self.trackers.push(ProbabilisticYin::new(range));
+        }
+
+        let mut output = PsolaOutput {
+            dry: vec![],
+            voices: vec![],
+        };
+        for c in 0..channels {
+            let input = &mut self.inputs[c];
+            input.samples.extend_from_slice(chunk.samples(c));
+            let end = input.end() - delay as isize;
+            let synthesis_end = end + max_period as isize;
+
+            // new voices join at the current position
+            let position = end - duration as isize;
+            self.voices[c].truncate(voices);
+            while self.voices[c].len() < voices {
+                self.voices[c].push(PsolaVoice::new(max_period / 2, position));
+            }
+
+            // the pitch is estimated around the grains to be placed
+            let analysis_len = duration.max(max_period * 3) as isize;
+            let tracker = &mut self.trackers[c];
+            tracker.set_range(range);
+            let estimate = tracker
+                .estimate(
+                    input.range(synthesis_end - analysis_len, synthesis_end),
+                    sample_rate,
+                )
+                .filter(|p| p.confidence >= voicing_threshold);
+            let period = estimate
+                .map(|p| p.period(sample_rate).round() as usize)
+                .filter(|p| *p > 0);
+
+            let mut keep_from = end - analysis_len;
+            let mut voice_samples = vec![];
+            for (v, voice) in self.voices[c].iter_mut().enumerate() {
+                let r = ratio(c, v, estimate);
+                voice.synthesize(input, synthesis_end, period, r, window_function);
+                voice_samples.push(voice.emit(end, duration));
+                keep_from = keep_from.min(voice.in_center - max_period as isize);
+            }
+            output
+                .dry
+                .push((position..end).map(|t| input.at(t)).collect());
+            output.voices.push(voice_samples);
+            input.forget(keep_from);
+        }
+        output
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PitchCorrection {
+    pub enabled: bool,
+    // 0 = C
+    pub key: usize,
+    pub scale: Scale,
+    pub custom_notes: [bool; 12],
+    // time in ms to glide to the target note; 0 snaps instantly
+    pub retune_speed: f32,
+}
+
+impl Default for PitchCorrection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            key: 0,
+            scale: Scale::Chromatic,
+            custom_notes: [true; 12],
+            retune_speed: 50.0,
+        }
+    }
+}
+
+impl PitchCorrection {
+    // the correction in semitones that brings `frequency` transposed by `ratio` onto the scale
+    fn target(&self, frequency: f32, ratio: f32) -> f32 {
+        let degrees = self.scale.degrees(&self.custom_notes);
+        let transposed = frequency * ratio;
+        match quantize_pitch(transposed, self.key, &degrees) {
+            Some(target) => frequency_to_note(target) - frequency_to_note(transposed),
+            None => 0.0,
+        }
+    }
 }
 
 #[derive(Getters, Serialize, Deserialize, Debug)]
 pub struct PsolaNode {
     io: NodeIo,
     ratio: f32,
+    #[serde(default = "default_min_frequency")]
+    min_frequency: f32,
+    #[serde(default = "default_max_frequency")]
+    max_frequency: f32,
+    #[serde(default = "default_voicing_threshold")]
+    voicing_threshold: f32,
+    #[serde(default = "default_window_function")]
+    window_function: WindowFunction,
+    #[serde(default)]
+    correction: PitchCorrection,
+    #[serde(skip)]
+    engine: PsolaEngine,
+    // current pitch correction of each channel in semitones
     #[serde(skip)]
-    psola_info: Vec<PsolaInfo>,
+    corrections: Vec<f32>,
     id: NodeId,
 }
 
+fn default_min_frequency() -> f32 {
+    DEFAULT_PITCH_RANGE.0
+}
+
+fn default_max_frequency() -> f32 {
+    DEFAULT_PITCH_RANGE.1
+}
+
+fn default_voicing_threshold() -> f32 {
+    DEFAULT_VOICING_THRESHOLD
+}
+
+fn default_window_function() -> WindowFunction {
+    WindowFunction::Hanning
+}
+
 impl HasNodeIo for PsolaNode {
     fn node_io(&self) -> &NodeIo {
         &self.io
@@ -34,6 +347,9 @@ impl NodeTrait for PsolaNode {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
@@ -53,7 +369,13 @@ impl PsolaNode {
         Self {
             io: NodeIo::new(),
             ratio,
-            psola_info: vec![],
+            min_frequency: default_min_frequency(),
+            max_frequency: default_max_frequency(),
+            voicing_threshold: default_voicing_threshold(),
+            window_function: default_window_function(),
+            correction: PitchCorrection::default(),
+            engine: PsolaEngine::new(),
+            corrections: vec![],
             id: NodeId::new(),
         }
     }
@@ -66,40 +388,40 @@ impl PsolaNode {
         &mut self.ratio
     }
 
-    pub fn period(
-        data: &[f32],
-        period_lims: (usize, usize),
-        minimum_autocorrelation: f32,
-    ) -> Option<usize> {
-        // calculate autocorrelation using FFT
-        let fft = {
-            let mut planner = FFTplanner::new(false);
-            planner.plan_fft(data.len())
-        };
-        let ifft = {
-            let mut planner = FFTplanner::new(true);
-            planner.plan_fft(data.len())
-        };
-        let mut signal = data
-            .iter()
-            .map(|d| Complex32::new(*d, 0.0))
-            .collect::<Vec<_>>();
-        let mut spectrum = signal.clone();
-        fft.process(&mut signal, &mut spectrum);
-        spectrum[0] = Complex32::new(0.0, 0.0); // ignore DC content
-        let mut prod = spectrum.iter().map(|c| *c * c.conj()).collect::<Vec<_>>();
-        let mut coefs = signal.clone();
-        ifft.process(&mut prod, &mut coefs);
-
-        let mut max = 0.0;
-        let mut period = None;
-        for (i, c) in coefs.iter().map(|c| c.re).enumerate() {
-            if max < c && minimum_autocorrelation < c && period_lims.0 <= i && i <= period_lims.1 {
-                max = c;
-                period = Some(i);
-            }
-        }
-        period
+    pub fn min_frequency_mut(&mut self) -> &mut f32 {
+        &mut self.min_frequency
+    }
+
+    pub fn max_frequency_mut(&mut self) -> &mut f32 {
+        &mut self.max_frequency
+    }
+
+    // minimum confidence of the pitch estimate for a chunk to be treated as voiced
+    pub fn voicing_threshold_mut(&mut self) -> &mut f32 {
+        &mut self.voicing_threshold
+    }
+
+    pub fn window_function_mut(&mut self) -> &mut WindowFunction {
+        &mut self.window_function
+    }
+
+    pub fn correction_mut(&mut self) -> &mut PitchCorrection {
+        &mut self.correction
+    }
+
+    fn pitch_range(&self) -> (f32, f32) {
+        let min = self.min_frequency.max(1.0);
+        (min, self.max_frequency.max(min * 2.0))
+    }
+
+    pub fn period(data: &[f32], sample_rate: usize) -> Option<usize> {
+        yin(
+            data,
+            sample_rate,
+            DEFAULT_PITCH_RANGE,
+            DEFAULT_YIN_THRESHOLD,
+        )
+        .map(|p| p.period(sample_rate).round() as usize)
     }
 
     fn triangular_window(x: usize, length: usize) -> f32 {
@@ -112,78 +434,15 @@ impl PsolaNode {
         0.5 - 0.5 * (2.0 * 3.141592 * x).cos()
     }
 
-    fn unitary_ola(
-        data: &[f32],
-        result: &mut [f32],
-        in_center: isize,
-        in_period: usize,
-        out_center: isize,
-    ) {
-        for i in in_center - in_period as isize..in_center + in_period as isize {
-            let amplitude = {
-                let mut i = i;
-                while i < 0 {
-                    i += in_period as isize;
-                }
-                while i >= data.len() as isize {
-                    i -= in_period as isize;
-                }
-                if i < 0 {
-                    panic!("too short data");
-                }
-                data[i as usize]
-            };
-            let d = i - in_center;
-            let k = out_center + d;
-            if k >= 0 && k < result.len() as isize {
-                result[k as usize] += amplitude
-                    * Self::hanning_window(
-                        (i - (in_center - in_period as isize)) as usize,
-                        in_period * 2,
-                    );
-            }
-        }
+    fn rectangular_window(_x: usize, _length: usize) -> f32 {
+        1.0
     }
 
-    fn psola(&self, data: &[f32], info: &PsolaInfo) -> (Vec<f32>, PsolaInfo) {
-        if let Some(in_period) = Self::period(
-            data,
-            (50, 800),
-            data.iter().fold(0.0, |a, b| f32::max(a * a, b * b)) / 4.0,
-        ) {
-            let mut result = vec![0.0; data.len()];
-            let ratio = self.ratio;
-            Self::unitary_ola(
-                data,
-                &mut result,
-                info.last_in_center - data.len() as isize,
-                info.in_period as usize,
-                info.last_out_center - data.len() as isize,
-            );
-
-            let in_period = in_period as isize;
-            let out_period = (in_period as f32 / ratio) as isize;
-            let mut in_center = info.last_in_center - data.len() as isize + in_period;
-            let mut out_center = info.last_out_center - data.len() as isize + out_period;
-            while out_center < result.len() as isize {
-                while (in_center + in_period - out_center).abs() < (in_center - out_center).abs()
-                    && in_center + in_period < data.len() as isize
-                {
-                    in_center += in_period;
-                }
-                Self::unitary_ola(data, &mut result, in_center, in_period as usize, out_center);
-                out_center += out_period;
-            }
-
-            Self::unitary_ola(data, &mut result, in_center, in_period as usize, out_center);
-            let info = PsolaInfo {
-                last_in_center: in_center,
-                last_out_center: out_center - out_period,
-                in_period: in_period as isize,
-            };
-            (result, info)
-        } else {
-            (data.to_vec(), info.clone())
+    fn window(window_function: WindowFunction, x: usize, length: usize) -> f32 {
+        match window_function {
+            WindowFunction::Rectangular => Self::rectangular_window(x, length),
+            WindowFunction::Triangular => Self::triangular_window(x, length),
+            WindowFunction::Hanning => Self::hanning_window(x, length),
         }
     }
 
@@ -196,24 +455,140 @@ impl PsolaNode {
             }
         };
         let channels = *chunk.metadata().channels();
-        while self.psola_info.len() < channels {
-            self.psola_info.push(PsolaInfo {
-                last_out_center: 0,
-                last_in_center: 0,
-                in_period: 0,
-            });
-        }
-        let (samples, info) = (0..channels)
-            .zip(self.psola_info.iter())
-            .map(|(c, info)| self.psola(chunk.samples(c), info))
-            .unzip();
-        let out_chunk = DataChunk::Real(GenericDataChunk::new(
+        let sample_rate = *chunk.metadata().sample_rate();
+        let duration = *chunk.duration();
+        self.corrections.resize(channels, 0.0);
+
+        let correction = &self.correction;
+        let retune = if correction.retune_speed > 0.0 {
+            let chunk_ms = duration as f32 * 1000.0 / sample_rate as f32;
+            1.0 - (-chunk_ms / correction.retune_speed).exp()
+        } else {
+            1.0
+        };
+        let (ratio, range) = (self.ratio, self.pitch_range());
+        let corrections = &mut self.corrections;
+        let output = self.engine.process(
+            &chunk,
+            1,
+            range,
+            self.voicing_threshold,
+            self.window_function,
+            |c, _, estimate| {
+                // the correction holds its last value through unvoiced chunks
+                if !correction.enabled {
+                    corrections[c] = 0.0;
+                } else if let Some(p) = estimate {
+                    let target = correction.target(p.frequency, ratio);
+                    corrections[c] += (target - corrections[c]) * retune;
+                }
+                ratio * 2.0f32.powf(corrections[c] / 12.0)
+            },
+        );
+        let samples = output
+            .voices
+            .into_iter()
+            .map(|mut voices| voices.remove(0))
+            .collect();
+        Some(DataChunk::Real(GenericDataChunk::new(
             samples,
             chunk.metadata().clone(),
-            *chunk.duration(),
+            duration,
             chunk.window_info().clone(),
-        ));
-        self.psola_info = info;
-        Some(out_chunk)
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_discontinuities_at_chunk_boundaries() {
+        let sample_rate = 44100;
+        let chunk_size = 512;
+        let chunks = 120;
+        // a sine sweep from 150 Hz to 300 Hz
+        let len = chunk_size * chunks;
+        let mut phase = 0.0f32;
+        let sweep = (0..len)
+            .map(|i| {
+                let frequency = 150.0 + 150.0 * i as f32 / len as f32;
+                phase += 2.0 * std::f32::consts::PI * frequency / sample_rate as f32;
+                0.5 * phase.sin()
+            })
+            .collect::<Vec<_>>();
+
+        let mut node = PsolaNode::new(1.25);
+        let mut output = vec![];
+        for chunk in sweep.chunks(chunk_size) {
+            let chunk = DataChunk::Real(
+                GenericDataChunk::from_flat_sata(chunk, AudioMetadata::new(1, sample_rate))
+                    .unwrap(),
+            );
+            match node.process_chunk(chunk) {
+                Some(DataChunk::Real(chunk)) => output.extend_from_slice(chunk.samples(0)),
+                _ => panic!(),
+            }
+        }
+        assert_eq!(output.len(), len);
+
+        // skip the latency and the start up of the pitch tracker
+        let start = chunk_size * 20;
+        let steps = (start..len)
+            .map(|i| (i, (output[i] - output[i - 1]).abs()))
+            .collect::<Vec<_>>();
+        let max_step = steps
+            .iter()
+            .filter(|(i, _)| i % chunk_size != 0)
+            .fold(0.0f32, |m, (_, d)| m.max(*d));
+        let max_boundary_step = steps
+            .iter()
+            .filter(|(i, _)| i % chunk_size == 0)
+            .fold(0.0f32, |m, (_, d)| m.max(*d));
+        assert!(max_step > 0.0);
+        assert!(
+            max_boundary_step <= max_step * 1.1,
+            "{} > {}",
+            max_boundary_step,
+            max_step
+        );
+    }
+
+    #[test]
+    fn corrected_output_lands_on_the_scale_after_transposing() {
+        let sample_rate = 44100;
+        let chunk_size = 512;
+        let len = chunk_size * 80;
+        let input = (0..len)
+            .map(|i| {
+                0.5 * (2.0 * std::f32::consts::PI * 220.0 * i as f32 / sample_rate as f32).sin()
+            })
+            .collect::<Vec<_>>();
+        // 0.7 semitones up leaves A3 closest to A#3
+        let mut node = PsolaNode::new(2.0f32.powf(0.7 / 12.0));
+        node.correction_mut().enabled = true;
+        node.correction_mut().retune_speed = 0.0;
+        let mut output = vec![];
+        for chunk in input.chunks(chunk_size) {
+            let chunk = DataChunk::Real(
+                GenericDataChunk::from_flat_sata(chunk, AudioMetadata::new(1, sample_rate))
+                    .unwrap(),
+            );
+            match node.process_chunk(chunk) {
+                Some(DataChunk::Real(chunk)) => output.extend_from_slice(chunk.samples(0)),
+                _ => panic!(),
+            }
+        }
+        let p = yin(
+            &output[len - 4096..],
+            sample_rate,
+            DEFAULT_PITCH_RANGE,
+            0.15,
+        )
+        .unwrap();
+        // A#3 is MIDI note 58
+        let error = frequency_to_note(p.frequency) - 58.0;
+        assert!(error.abs() < 0.1, "{} Hz", p.frequency);
     }
 }
@@ -29,6 +29,9 @@ impl NodeTrait for PeriodReplicator {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
@@ -64,11 +67,7 @@ impl PeriodReplicator {
                 DataChunk::Real(chunk) => chunk.samples(0),
                 _ => unreachable!(),
             };
-            if let Some(period) = PsolaNode::period(
-                data,
-                (50, 800),
-                data.iter().fold(0.0, |a, b| f32::max(a * a, b * b)) / 4.0,
-            ) {
+            if let Some(period) = PsolaNode::period(data, *chunk.metadata().sample_rate()) {
                 self.wave = Some(
                     data.iter()
                         .skip((data.len() / 2 - period / 2).max(0))
@@ -0,0 +1,155 @@
+use super::super::common::*;
+use super::node::*;
+use super::oscillator::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+// Multiplies the input by an internal oscillator.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct RingModulatorNode {
+    io: NodeIo,
+    id: NodeId,
+    waveform: Waveform,
+    // Hz
+    frequency: f32,
+    // 0 for the dry input, 1 for the modulated signal only
+    mix: f32,
+    #[serde(skip)]
+    phase: f64,
+}
+
+impl HasNodeIo for RingModulatorNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl RingModulatorNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            waveform: Waveform::Sine,
+            frequency: 100.0,
+            mix: 1.0,
+            phase: 0.0,
+        }
+    }
+
+    pub fn waveform_mut(&mut self) -> &mut Waveform {
+        &mut self.waveform
+    }
+
+    pub fn frequency_mut(&mut self) -> &mut f32 {
+        &mut self.frequency
+    }
+
+    pub fn mix_mut(&mut self) -> &mut f32 {
+        &mut self.mix
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) if chunk.window_info().is_none() => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate() as f64;
+        let duration = *chunk.duration();
+        let dt = (self.frequency as f64 / sample_rate).max(0.0).min(0.5);
+        let mix = self.mix.max(0.0).min(1.0);
+        // the square of the pulse is fixed at half the period
+        let carrier = (0..duration)
+            .map(|_| {
+                let value = waveform_sample(self.waveform, self.phase, dt, 0.5);
+                self.phase = (self.phase + dt).fract();
+                value
+            })
+            .collect::<Vec<_>>();
+        let samples = (0..channels)
+            .map(|c| {
+                chunk
+                    .samples(c)
+                    .iter()
+                    .zip(carrier.iter())
+                    .map(|(x, m)| x * (1.0 - mix + mix * m))
+                    .collect()
+            })
+            .collect();
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            duration,
+            None,
+        )))
+    }
+}
+
+impl NodeTrait for RingModulatorNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testutil::*;
+    use super::*;
+
+    const SAMPLE_RATE: usize = 16000;
+
+    #[test]
+    fn sine_carrier_makes_sidebands() {
+        let input = (0..SAMPLE_RATE)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect::<Vec<_>>();
+        let run = |mix: f32| {
+            let mut node = RingModulatorNode::new();
+            *node.frequency_mut() = 300.0;
+            *node.mix_mut() = mix;
+            let mut output = vec![];
+            for samples in input.chunks(400) {
+                let chunk =
+                    GenericDataChunk::from_flat_sata(samples, AudioMetadata::new(1, SAMPLE_RATE))
+                        .unwrap();
+                match node.process_chunk(DataChunk::Real(chunk)) {
+                    Some(DataChunk::Real(chunk)) => output.extend_from_slice(chunk.samples(0)),
+                    _ => panic!(),
+                }
+            }
+            output
+        };
+
+        let output = run(1.0);
+        assert!((magnitude(&output, 700.0, SAMPLE_RATE) - 0.5).abs() < 0.01);
+        assert!((magnitude(&output, 1300.0, SAMPLE_RATE) - 0.5).abs() < 0.01);
+        assert!(magnitude(&output, 1000.0, SAMPLE_RATE) < 0.01);
+
+        let output = run(0.5);
+        assert!((magnitude(&output, 1000.0, SAMPLE_RATE) - 0.5).abs() < 0.01);
+        assert!((magnitude(&output, 1300.0, SAMPLE_RATE) - 0.25).abs() < 0.01);
+
+        assert_eq!(run(0.0), input);
+    }
+}
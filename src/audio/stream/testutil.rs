@@ -0,0 +1,26 @@
+// Signals and measurements shared by the tests of the nodes.
+
+// uniform in [0, 1), from a linear congruential generator
+pub fn random(seed: &mut u32) -> f32 {
+    *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+    (*seed >> 8) as f32 / (1 << 24) as f32
+}
+
+// white noise uniform in [-amplitude, amplitude)
+pub fn noise(seed: &mut u32, amplitude: f32, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|_| amplitude * (2.0 * random(seed) - 1.0))
+        .collect()
+}
+
+// amplitude of the component at `frequency`, by a single-bin DFT
+pub fn magnitude(samples: &[f32], frequency: f32, sample_rate: usize) -> f32 {
+    let (re, im) = samples
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (i, s)| {
+            let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32;
+            (re + s * phase.cos(), im + s * phase.sin())
+        });
+    2.0 * (re * re + im * im).sqrt() / samples.len() as f32
+}
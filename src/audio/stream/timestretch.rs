@@ -0,0 +1,327 @@
+use super::super::common::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+// Streaming WSOLA. Positions are absolute sample indices of the input stream.
+#[derive(Clone, Debug)]
+struct WsolaState {
+    frame: usize,
+    sample_rate: usize,
+    input: Vec<Vec<f32>>,
+    input_start: isize,
+    // nominal start of the next analysis frame
+    position: f64,
+    // start of the previously chosen analysis frame
+    prev: Option<isize>,
+    // second half of the previous windowed frame, still to be overlapped
+    tail: Vec<Vec<f32>>,
+    pending: Vec<Vec<f32>>,
+}
+
+impl WsolaState {
+    fn new(channels: usize, sample_rate: usize, frame: usize) -> Self {
+        let frame = (frame / 2).max(2) * 2;
+        Self {
+            frame,
+            sample_rate,
+            input: vec![vec![]; channels],
+            input_start: 0,
+            position: 0.0,
+            prev: None,
+            tail: vec![vec![0.0; frame / 2]; channels],
+            pending: vec![vec![]; channels],
+        }
+    }
+
+    fn input_end(&self) -> isize {
+        self.input_start + self.input[0].len() as isize
+    }
+
+    // the channels mixed down, which is what frames are aligned by
+    fn mix_at(&self, t: isize) -> f32 {
+        let i = (t - self.input_start) as usize;
+        self.input.iter().map(|c| c[i]).sum()
+    }
+
+    fn similarity(&self, a: isize, b: isize, len: usize) -> f32 {
+        (0..len as isize)
+            .map(|i| self.mix_at(a + i) * self.mix_at(b + i))
+            .sum()
+    }
+
+    // synthesizes one hop of output if enough input is buffered
+    fn step(&mut self, stretch: f32) -> bool {
+        let hop = self.frame / 2;
+        let tolerance = (self.frame / 4) as isize;
+        let nominal = self.position.round() as isize;
+        if nominal + tolerance + self.frame as isize > self.input_end() {
+            return false;
+        }
+        // the frame most similar to the natural continuation of the previous one
+        let chosen = match self.prev {
+            None => nominal,
+            Some(prev) => {
+                let natural = prev + hop as isize;
+                let lowest = (nominal - tolerance).max(self.input_start);
+                let first = (lowest, self.similarity(natural, lowest, hop));
+                let (best, _) = (lowest + 1..=nominal + tolerance).fold(
+                    first,
+                    |(best, best_score), candidate| {
+                        let score = self.similarity(natural, candidate, hop);
+                        if score > best_score {
+                            (candidate, score)
+                        } else {
+                            (best, best_score)
+                        }
+                    },
+                );
+                best
+            }
+        };
+
+        let pi = std::f32::consts::PI;
+        let offset = (chosen - self.input_start) as usize;
+        for c in 0..self.input.len() {
+            let frame = (0..self.frame)
+                .map(|i| {
+                    let window = 0.5 - 0.5 * (2.0 * pi * i as f32 / self.frame as f32).cos();
+                    self.input[c][offset + i] * window
+                })
+                .collect::<Vec<_>>();
+            let overlapped = self.tail[c].iter().zip(frame.iter()).map(|(t, f)| t + f);
+            self.pending[c].extend(overlapped);
+            self.tail[c] = frame[hop..].to_vec();
+        }
+        self.prev = Some(chosen);
+        self.position += hop as f64 / stretch.max(0.01) as f64;
+        true
+    }
+
+    fn forget_input(&mut self) {
+        let hop = (self.frame / 2) as isize;
+        let tolerance = (self.frame / 4) as isize;
+        let mut keep_from = self.position.round() as isize - tolerance;
+        if let Some(prev) = self.prev {
+            keep_from = keep_from.min(prev + hop);
+        }
+        let n = (keep_from - self.input_start)
+            .max(0)
+            .min(self.input[0].len() as isize) as usize;
+        for c in self.input.iter_mut() {
+            c.drain(..n);
+        }
+        self.input_start += n as isize;
+    }
+
+    fn take_pending(&mut self, len: usize) -> Vec<Vec<f32>> {
+        self.pending
+            .iter_mut()
+            .map(|p| {
+                let n = len.min(p.len());
+                let mut samples = p.drain(..n).collect::<Vec<_>>();
+                samples.resize(len, 0.0);
+                samples
+            })
+            .collect()
+    }
+}
+
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct TimeStretchNode {
+    io: NodeIo,
+    id: NodeId,
+    // output duration relative to the input
+    stretch: f32,
+    window_ms: f32,
+    // whether chunks keep their size so that the node can run against an audio device
+    real_time: bool,
+    // how far the input may fall behind in real time before it is skipped
+    max_buffer_ms: f32,
+    #[serde(skip)]
+    state: Option<WsolaState>,
+}
+
+impl HasNodeIo for TimeStretchNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl TimeStretchNode {
+    pub fn new(stretch: f32) -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            stretch,
+            window_ms: 30.0,
+            real_time: true,
+            max_buffer_ms: 500.0,
+            state: None,
+        }
+    }
+
+    pub fn stretch_mut(&mut self) -> &mut f32 {
+        &mut self.stretch
+    }
+
+    pub fn window_ms_mut(&mut self) -> &mut f32 {
+        &mut self.window_ms
+    }
+
+    pub fn real_time(&self) -> bool {
+        self.real_time
+    }
+
+    pub fn real_time_mut(&mut self) -> &mut bool {
+        &mut self.real_time
+    }
+
+    pub fn max_buffer_ms_mut(&mut self) -> &mut f32 {
+        &mut self.max_buffer_ms
+    }
+
+    // input samples waiting to be stretched
+    pub fn buffered(&self) -> usize {
+        match &self.state {
+            Some(state) => (state.input_end() - state.position.round() as isize).max(0) as usize,
+            None => 0,
+        }
+    }
+
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) if chunk.window_info().is_none() => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let duration = *chunk.duration();
+        let frame = (self.window_ms * sample_rate as f32 / 1000.0) as usize;
+        let compatible = match &self.state {
+            Some(state) => {
+                state.input.len() == channels
+                    && state.sample_rate == sample_rate
+                    && state.frame == (frame / 2).max(2) * 2
+            }
+            None => false,
+        };
+        if !compatible {
+            self.state = Some(WsolaState::new(channels, sample_rate, frame));
+        }
+        let max_buffer = (self.max_buffer_ms * sample_rate as f32 / 1000.0) as usize;
+        let (stretch, real_time) = (self.stretch, self.real_time);
+        let state = self.state.as_mut().unwrap();
+
+        for c in 0..channels {
+            state.input[c].extend_from_slice(chunk.samples(c));
+        }
+        if real_time {
+            let backlog = state.input_end() - state.position.round() as isize;
+            if backlog > (max_buffer + state.frame) as isize {
+                state.position = (state.input_end() - max_buffer as isize / 2) as f64;
+            }
+        }
+        while !(real_time && state.pending[0].len() >= duration) && state.step(stretch) {}
+        state.forget_input();
+
+        let len = if real_time {
+            duration
+        } else {
+            state.pending[0].len()
+        };
+        if len == 0 {
+            return None;
+        }
+        Some(DataChunk::Real(GenericDataChunk::new(
+            state.take_pending(len),
+            chunk.metadata().clone(),
+            len,
+            None,
+        )))
+    }
+}
+
+impl NodeTrait for TimeStretchNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some(chunk) = self.process_chunk(chunk) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::pitch::yin;
+
+    fn tone(len: usize, sample_rate: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * 220.0 * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn stretch(node: &mut TimeStretchNode, input: &[f32], sample_rate: usize) -> Vec<f32> {
+        let mut output = vec![];
+        for chunk in input.chunks(512) {
+            let chunk = DataChunk::Real(
+                GenericDataChunk::from_flat_sata(chunk, AudioMetadata::new(1, sample_rate))
+                    .unwrap(),
+            );
+            if let Some(DataChunk::Real(chunk)) = node.process_chunk(chunk) {
+                output.extend_from_slice(chunk.samples(0));
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn offline_stretch_keeps_pitch() {
+        let sample_rate = 16000;
+        let input = tone(sample_rate * 2, sample_rate);
+        let mut node = TimeStretchNode::new(1.5);
+        *node.real_time_mut() = false;
+        let output = stretch(&mut node, &input, sample_rate);
+        let expected = input.len() as f32 * 1.5;
+        assert!((output.len() as f32 - expected).abs() < sample_rate as f32 * 0.05);
+        let middle = output.len() / 2;
+        let p = yin(
+            &output[middle..middle + 2048],
+            sample_rate,
+            (60.0, 800.0),
+            0.15,
+        )
+        .unwrap();
+        assert!((p.frequency - 220.0).abs() < 2.0, "{:?}", p);
+    }
+
+    #[test]
+    fn real_time_buffer_is_bounded() {
+        let sample_rate = 16000;
+        let input = tone(sample_rate * 4, sample_rate);
+        let mut node = TimeStretchNode::new(2.0);
+        let output = stretch(&mut node, &input, sample_rate);
+        assert_eq!(output.len(), input.len());
+        let max_buffer = (500.0 * sample_rate as f32 / 1000.0) as usize;
+        assert!(node.buffered() <= max_buffer + 512 + 480);
+    }
+}
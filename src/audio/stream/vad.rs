@@ -0,0 +1,374 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::node::*;
+use getset::Getters;
+use rustfft::num_complex::Complex32;
+use rustfft::num_traits::Zero;
+use serde::{Deserialize, Serialize};
+
+const HISTORY_LENGTH: usize = 256;
+// the noise floor drops quickly to quieter frames and creeps up during pauses
+const NOISE_FLOOR_FALL_MS: f32 = 50.0;
+const NOISE_FLOOR_RISE_MS: f32 = 5000.0;
+const FADE_MS: f32 = 10.0;
+
+// per-frame coefficient of a time constant when frames of `duration` samples arrive
+fn frame_coefficient(ms: f32, duration: usize, sample_rate: usize) -> f32 {
+    time_coefficient(ms / duration.max(1) as f32, sample_rate)
+}
+
+// geometric over arithmetic mean of the power spectrum in dB, 0 dB for white noise
+pub fn spectral_flatness(frame: &[f32], fft_plans: &mut FftPlans) -> f32 {
+    let n = frame.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let pi = std::f32::consts::PI;
+    let mut input = frame
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            Complex32::new(
+                s * (0.5 - 0.5 * (2.0 * pi * i as f32 / n as f32).cos()),
+                0.0,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut spectrum = vec![Complex32::zero(); n];
+    fft_plans.get(n, false).process(&mut input, &mut spectrum);
+    // the DC bin is left out
+    let powers = spectrum[1..=n / 2]
+        .iter()
+        .map(|s| s.norm_sqr().max(1e-20))
+        .collect::<Vec<_>>();
+    let log_mean = powers.iter().map(|p| p.ln()).sum::<f32>() / powers.len() as f32;
+    let mean = powers.iter().sum::<f32>() / powers.len() as f32;
+    10.0 * (log_mean.exp() / mean).log10()
+}
+
+// sign changes per sample
+pub fn zero_crossing_rate(frame: &[f32]) -> f32 {
+    if frame.len() < 2 {
+        return 0.0;
+    }
+    let crossings = frame
+        .windows(2)
+        .filter(|w| (w[0] >= 0.0) != (w[1] >= 0.0))
+        .count();
+    crossings as f32 / (frame.len() - 1) as f32
+}
+
+// Detects speech in each chunk from its energy above the noise floor, its zero-crossing rate
+// and its spectral flatness. A chunk is speech when two of the three agree.
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct VadNode {
+    io: NodeIo,
+    id: NodeId,
+    // dB above the noise floor
+    energy_threshold: f32,
+    // dB, below which nothing is speech
+    min_level: f32,
+    zcr_threshold: f32,
+    // dB
+    flatness_threshold: f32,
+    // ms for which speech is assumed to continue after the last detection
+    hangover: f32,
+    // dB applied between utterances
+    attenuation: f32,
+    #[serde(skip)]
+    noise_floor: Option<f32>,
+    #[serde(skip)]
+    active: bool,
+    #[serde(skip)]
+    hangover_left: usize,
+    #[serde(skip)]
+    gain: EnvelopeFollower,
+    // 1 while speech, oldest first
+    #[serde(skip)]
+    activity_history: Vec<f32>,
+    #[serde(skip)]
+    energy_history: Vec<f32>,
+    #[serde(skip)]
+    fft_plans: FftPlans,
+}
+
+impl HasNodeIo for VadNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl VadNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            energy_threshold: 10.0,
+            min_level: -60.0,
+            zcr_threshold: 0.25,
+            flatness_threshold: -6.0,
+            hangover: 200.0,
+            attenuation: -60.0,
+            noise_floor: None,
+            active: false,
+            hangover_left: 0,
+            gain: EnvelopeFollower::default(),
+            activity_history: vec![],
+            energy_history: vec![],
+            fft_plans: FftPlans::default(),
+        }
+    }
+
+    pub fn energy_threshold_mut(&mut self) -> &mut f32 {
+        &mut self.energy_threshold
+    }
+
+    pub fn min_level_mut(&mut self) -> &mut f32 {
+        &mut self.min_level
+    }
+
+    pub fn zcr_threshold_mut(&mut self) -> &mut f32 {
+        &mut self.zcr_threshold
+    }
+
+    pub fn flatness_threshold_mut(&mut self) -> &mut f32 {
+        &mut self.flatness_threshold
+    }
+
+    pub fn hangover_mut(&mut self) -> &mut f32 {
+        &mut self.hangover
+    }
+
+    pub fn attenuation_mut(&mut self) -> &mut f32 {
+        &mut self.attenuation
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn noise_floor(&self) -> Option<f32> {
+        self.noise_floor
+    }
+
+    pub fn activity_history(&self) -> &[f32] {
+        &self.activity_history
+    }
+
+    pub fn energy_history(&self) -> &[f32] {
+        &self.energy_history
+    }
+
+    // the fraction of the features which indicate speech
+    fn score(&mut self, frame: &[f32], duration: usize, sample_rate: usize) -> f32 {
+        let energy = amplitude_to_db(
+            (frame.iter().map(|s| s * s).sum::<f32>() / frame.len().max(1) as f32).sqrt(),
+        );
+        let floor = match self.noise_floor {
+            Some(floor) if energy < floor => {
+                let fall = frame_coefficient(NOISE_FLOOR_FALL_MS, duration, sample_rate);
+                energy + fall * (floor - energy)
+            }
+            Some(floor) if !self.active => {
+                let rise = frame_coefficient(NOISE_FLOOR_RISE_MS, duration, sample_rate);
+                energy + rise * (floor - energy)
+            }
+            Some(floor) => floor,
+            None => energy,
+        };
+        self.noise_floor = Some(floor);
+        self.energy_history.push(energy);
+        if self.energy_history.len() > HISTORY_LENGTH {
+            self.energy_history.remove(0);
+        }
+        if energy < self.min_level {
+            return 0.0;
+        }
+        let votes = [
+            energy - floor > self.energy_threshold,
+            zero_crossing_rate(frame) < self.zcr_threshold,
+            spectral_flatness(frame, &mut self.fft_plans) < self.flatness_threshold,
+        ];
+        votes.iter().filter(|v| **v).count() as f32 / votes.len() as f32
+    }
+
+    // the gated audio and the decision as a control chunk
+    pub fn process_chunk(&mut self, chunk: DataChunk) -> Option<(DataChunk, DataChunk)> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) if chunk.window_info().is_none() => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let duration = *chunk.duration();
+        let mixed = (0..duration)
+            .map(|t| (0..channels).map(|c| chunk.samples(c)[t]).sum::<f32>() / channels as f32)
+            .collect::<Vec<_>>();
+
+        let score = self.score(&mixed, duration, sample_rate);
+        if score >= 0.5 {
+            self.active = true;
+            self.hangover_left = (self.hangover * sample_rate as f32 / 1000.0) as usize;
+        } else if self.hangover_left > 0 {
+            self.hangover_left = self.hangover_left.saturating_sub(duration);
+        } else {
+            self.active = false;
+        }
+        self.activity_history
+            .push(if self.active { 1.0 } else { 0.0 });
+        if self.activity_history.len() > HISTORY_LENGTH {
+            self.activity_history.remove(0);
+        }
+
+        let target = if self.active {
+            0.0
+        } else {
+            self.attenuation.min(0.0)
+        };
+        let fade = time_coefficient(FADE_MS, sample_rate);
+        let gains = (0..duration)
+            .map(|_| db_to_amplitude(self.gain.process(target, fade, fade)))
+            .collect::<Vec<_>>();
+        let samples = (0..channels)
+            .map(|c| {
+                chunk
+                    .samples(c)
+                    .iter()
+                    .zip(gains.iter())
+                    .map(|(s, g)| s * g)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let audio = DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            duration,
+            None,
+        ));
+        let value = ControlValue::Voice {
+            active: self.active,
+            score,
+        };
+        let control = DataChunk::Control(ControlChunk::new(
+            vec![value; channels],
+            chunk.metadata().clone(),
+            duration,
+        ));
+        Some((audio, control))
+    }
+}
+
+impl NodeTrait for VadNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn is_control_output(&self, index: usize) -> bool {
+        index == 1
+    }
+    // the second output carries the decision, the others the gated audio
+    fn run_once(&mut self) {
+        if self.inputs().len() != 1 {
+            return;
+        }
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            if let Some((audio, control)) = self.process_chunk(chunk) {
+                for (i, output) in self.outputs().iter().enumerate() {
+                    let chunk = if i == 1 { &control } else { &audio };
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testutil::*;
+    use super::*;
+
+    const SAMPLE_RATE: usize = 16000;
+    const DURATION: usize = 512;
+
+    fn voice(offset: usize) -> Vec<f32> {
+        (0..DURATION)
+            .map(|i| {
+                let t = (offset + i) as f32 / SAMPLE_RATE as f32;
+                (1..6)
+                    .map(|h| (2.0 * std::f32::consts::PI * 150.0 * h as f32 * t).sin() / h as f32)
+                    .sum::<f32>()
+                    * 0.2
+            })
+            .collect()
+    }
+
+    fn run(vad: &mut VadNode, samples: &[f32]) -> (f32, bool) {
+        let chunk = DataChunk::Real(
+            GenericDataChunk::from_flat_sata(samples, AudioMetadata::new(1, SAMPLE_RATE)).unwrap(),
+        );
+        match vad.process_chunk(chunk) {
+            Some((DataChunk::Real(audio), DataChunk::Control(control))) => {
+                let peak = audio.samples(0).iter().fold(0.0f32, |m, s| m.max(s.abs()));
+                match control.value(0) {
+                    ControlValue::Voice { active, .. } => (peak, *active),
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn features_tell_tones_from_noise() {
+        let mut seed = 1;
+        let mut fft_plans = FftPlans::default();
+        let white = noise(&mut seed, 0.5, DURATION);
+        assert!(zero_crossing_rate(&white) > 0.4);
+        assert!(spectral_flatness(&white, &mut fft_plans) > -4.0);
+        let tone = voice(0);
+        assert!(zero_crossing_rate(&tone) < 0.1);
+        assert!(spectral_flatness(&tone, &mut fft_plans) < -10.0);
+    }
+
+    #[test]
+    fn gates_between_utterances() {
+        let mut seed = 1;
+        let mut vad = VadNode::new();
+        for i in 0..20 {
+            let (peak, active) = run(&mut vad, &noise(&mut seed, 0.01, DURATION));
+            assert!(!active);
+            // once faded out
+            if i > 0 {
+                assert!(peak < 0.001);
+            }
+        }
+        for i in 0..10 {
+            let samples = voice(i * DURATION)
+                .iter()
+                .zip(noise(&mut seed, 0.01, DURATION).iter())
+                .map(|(v, n)| v + n)
+                .collect::<Vec<_>>();
+            let (peak, active) = run(&mut vad, &samples);
+            assert!(active);
+            if i > 0 {
+                assert!(peak > 0.2);
+            }
+        }
+        // the hangover of 200 ms spans six chunks
+        for _ in 0..6 {
+            assert!(run(&mut vad, &noise(&mut seed, 0.01, DURATION)).1);
+        }
+        for _ in 0..2 {
+            run(&mut vad, &noise(&mut seed, 0.01, DURATION));
+        }
+        assert!(!run(&mut vad, &noise(&mut seed, 0.01, DURATION)).1);
+    }
+}
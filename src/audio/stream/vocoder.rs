@@ -0,0 +1,451 @@
+use super::super::common::*;
+use super::super::dynamics::*;
+use super::super::rechunker::ChannelRechunker;
+use super::equalizer::*;
+use super::node::*;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// band-pass sections cascaded per band
+const SECTIONS: usize = 2;
+// level below which a carrier band is not normalized further
+const CARRIER_FLOOR: f32 = 1e-4;
+
+#[derive(Debug)]
+struct VocoderState {
+    channels: usize,
+    sample_rate: usize,
+    // channel, band, section
+    modulator_filters: Vec<Vec<[BiquadState; SECTIONS]>>,
+    carrier_filters: Vec<Vec<[BiquadState; SECTIONS]>>,
+    // channel, band
+    modulator_envelopes: Vec<Vec<EnvelopeFollower>>,
+    carrier_envelopes: Vec<Vec<EnvelopeFollower>>,
+}
+
+impl VocoderState {
+    fn new(channels: usize, sample_rate: usize, bands: usize) -> Self {
+        let filters = vec![vec![[BiquadState::default(); SECTIONS]; bands]; channels];
+        let envelopes = vec![vec![EnvelopeFollower::default(); bands]; channels];
+        Self {
+            channels,
+            sample_rate,
+            modulator_filters: filters.clone(),
+            carrier_filters: filters,
+            modulator_envelopes: envelopes.clone(),
+            carrier_envelopes: envelopes,
+        }
+    }
+
+    fn bands(&self) -> usize {
+        self.modulator_envelopes
+            .first()
+            .map(|e| e.len())
+            .unwrap_or(0)
+    }
+}
+
+fn filter(states: &mut [BiquadState; SECTIONS], c: &BiquadCoefficients, x: f32) -> f32 {
+    states.iter_mut().fold(x, |x, state| state.process(c, x))
+}
+
+// Imposes the band envelopes of the first input (modulator) onto the second (carrier).
+#[derive(Getters, Serialize, Deserialize, Debug)]
+pub struct ChannelVocoderNode {
+    io: NodeIo,
+    id: NodeId,
+    band_count: usize,
+    // Hz, centers of the lowest and highest bands
+    low: f32,
+    high: f32,
+    // ms
+    attack: f32,
+    release: f32,
+    // dB of each band
+    band_gains: Vec<f32>,
+    // divides each carrier band by its own envelope so that only its fine structure remains
+    normalize_carrier: bool,
+    #[serde(skip)]
+    state: Option<VocoderState>,
+    // modulator waiting for the carrier to catch up
+    #[serde(skip)]
+    pending: VecDeque<DataChunk>,
+    #[serde(skip)]
+    carrier: ChannelRechunker,
+}
+
+impl HasNodeIo for ChannelVocoderNode {
+    fn node_io(&self) -> &NodeIo {
+        &self.io
+    }
+    fn node_io_mut(&mut self) -> &mut NodeIo {
+        &mut self.io
+    }
+}
+
+impl ChannelVocoderNode {
+    pub fn new() -> Self {
+        Self {
+            io: NodeIo::new(),
+            id: NodeId::new(),
+            band_count: 16,
+            low: 100.0,
+            high: 8000.0,
+            attack: 5.0,
+            release: 30.0,
+            band_gains: vec![0.0; 16],
+            normalize_carrier: true,
+            state: None,
+            pending: VecDeque::new(),
+            carrier: ChannelRechunker::default(),
+        }
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.band_count
+    }
+
+    pub fn band_count_mut(&mut self) -> &mut usize {
+        &mut self.band_count
+    }
+
+    pub fn low_mut(&mut self) -> &mut f32 {
+        &mut self.low
+    }
+
+    pub fn high_mut(&mut self) -> &mut f32 {
+        &mut self.high
+    }
+
+    pub fn attack_mut(&mut self) -> &mut f32 {
+        &mut self.attack
+    }
+
+    pub fn release_mut(&mut self) -> &mut f32 {
+        &mut self.release
+    }
+
+    // one gain per band, kept as long as `band_count`
+    pub fn band_gains_mut(&mut self) -> &mut Vec<f32> {
+        let band_count = self.band_count.max(1);
+        self.band_gains.resize(band_count, 0.0);
+        &mut self.band_gains
+    }
+
+    pub fn normalize_carrier_mut(&mut self) -> &mut bool {
+        &mut self.normalize_carrier
+    }
+
+    // Hz, spaced evenly on a log scale
+    pub fn band_frequencies(&self) -> Vec<f32> {
+        let n = self.band_count.max(1);
+        let low = self.low.max(1.0);
+        let high = self.high.max(low);
+        (0..n)
+            .map(|i| {
+                let x = if n > 1 {
+                    i as f32 / (n - 1) as f32
+                } else {
+                    0.5
+                };
+                low * (high / low).powf(x)
+            })
+            .collect()
+    }
+
+    // envelope of each band of the first modulator channel in dB
+    pub fn modulator_levels(&self) -> Vec<f32> {
+        match &self.state {
+            Some(state) => state.modulator_envelopes[0]
+                .iter()
+                .map(|e| amplitude_to_db(e.value()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    fn coefficients(&self, sample_rate: usize) -> Vec<BiquadCoefficients> {
+        let frequencies = self.band_frequencies();
+        // neighbouring bands meet at their half-power points
+        let ratio = if frequencies.len() > 1 {
+            (frequencies[1] / frequencies[0]).max(1.01)
+        } else {
+            2.0
+        };
+        let q = ratio.sqrt() / (ratio - 1.0);
+        frequencies
+            .iter()
+            .map(|f| {
+                let mut band = EqBand::new(BandType::BandPass, *f);
+                band.q = q;
+                band.coefficients(sample_rate as f32)
+            })
+            .collect()
+    }
+
+    pub fn process_chunk(
+        &mut self,
+        chunk: DataChunk,
+        carrier: Option<DataChunk>,
+    ) -> Option<DataChunk> {
+        let chunk = match chunk {
+            DataChunk::Real(chunk) if chunk.window_info().is_none() => chunk,
+            _ => {
+                eprintln!("incompatible input {}: {}", file!(), line!());
+                return None;
+            }
+        };
+        let channels = *chunk.metadata().channels();
+        let sample_rate = *chunk.metadata().sample_rate();
+        let duration = *chunk.duration();
+        let carrier = match carrier {
+            Some(DataChunk::Real(carrier))
+                if *carrier.duration() == duration && carrier.window_info().is_none() =>
+            {
+                Some(carrier)
+            }
+            Some(_) => {
+                eprintln!("incompatible carrier {}: {}", file!(), line!());
+                None
+            }
+            None => None,
+        };
+
+        let bands = self.band_count.max(1);
+        let compatible = match &self.state {
+            Some(state) => {
+                state.channels == channels
+                    && state.sample_rate == sample_rate
+                    && state.bands() == bands
+            }
+            None => false,
+        };
+        if !compatible {
+            self.state = Some(VocoderState::new(channels, sample_rate, bands));
+        }
+        let coefficients = self.coefficients(sample_rate);
+        let gains = self
+            .band_gains_mut()
+            .iter()
+            .map(|g| db_to_amplitude(*g))
+            .collect::<Vec<_>>();
+        let attack = time_coefficient(self.attack, sample_rate);
+        let release = time_coefficient(self.release, sample_rate);
+        let normalize = self.normalize_carrier;
+        let state = self.state.as_mut().unwrap();
+
+        let samples = (0..channels)
+            .map(|c| {
+                // a mono carrier drives every channel
+                let carrier = carrier
+                    .as_ref()
+                    .map(|carrier| carrier.samples(c.min(*carrier.metadata().channels() - 1)));
+                (0..duration)
+                    .map(|t| {
+                        let m = chunk.samples(c)[t];
+                        let x = carrier.map(|carrier| carrier[t]).unwrap_or(0.0);
+                        (0..bands)
+                            .map(|b| {
+                                let band =
+                                    filter(&mut state.modulator_filters[c][b], &coefficients[b], m);
+                                let envelope = state.modulator_envelopes[c][b].process(
+                                    band.abs(),
+                                    attack,
+                                    release,
+                                );
+                                let band =
+                                    filter(&mut state.carrier_filters[c][b], &coefficients[b], x);
+                                let carrier_envelope = state.carrier_envelopes[c][b].process(
+                                    band.abs(),
+                                    attack,
+                                    release,
+                                );
+                                let band = if normalize {
+                                    band / carrier_envelope.max(CARRIER_FLOOR)
+                                } else {
+                                    band
+                                };
+                                band * envelope * gains[b]
+                            })
+                            .sum::<f32>()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Some(DataChunk::Real(GenericDataChunk::new(
+            samples,
+            chunk.metadata().clone(),
+            duration,
+            None,
+        )))
+    }
+}
+
+impl NodeTrait for ChannelVocoderNode {
+    fn id(&self) -> NodeId {
+        self.id
+    }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
+    fn run_once(&mut self) {
+        if self.inputs().is_empty() {
+            return;
+        }
+        // the carrier is taken in the lengths the modulator arrives in
+        while let Some(chunk) = self.inputs()[0].try_recv().ok() {
+            self.pending.push_back(chunk);
+        }
+        while let Some(carrier) = self.inputs().get(1).and_then(|p| p.try_recv().ok()) {
+            match carrier {
+                DataChunk::Real(carrier) if carrier.window_info().is_none() => {
+                    self.carrier.feed_chunk(carrier)
+                }
+                _ => eprintln!("incompatible carrier {}: {}", file!(), line!()),
+            }
+        }
+        let connected = self
+            .inputs()
+            .get(1)
+            .map(|p| p.rx.is_some())
+            .unwrap_or(false);
+        if !connected {
+            self.carrier.clear();
+        }
+        while let Some(chunk) = self.pending.pop_front() {
+            let carrier = if connected {
+                match self.carrier.pull_chunk(*chunk.duration()) {
+                    Some(carrier) => Some(DataChunk::Real(carrier)),
+                    None => {
+                        self.pending.push_front(chunk);
+                        break;
+                    }
+                }
+            } else {
+                None
+            };
+            if let Some(chunk) = self.process_chunk(chunk, carrier) {
+                for output in self.outputs().iter() {
+                    let _ = output.try_send(chunk.clone());
+                }
+            }
+        }
+    }
+    fn add_input(&mut self) -> Result<&mut InputPort, Box<dyn std::error::Error>> {
+        if self.inputs().len() < 2 {
+            let id = self.id();
+            self.node_io_mut().inputs_mut().push(InputPort::new(id));
+            let l = self.inputs().len();
+            Ok(&mut self.inputs_mut()[l - 1])
+        } else {
+            Err(Box::new(PortAdditionError))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::testutil::*;
+    use super::*;
+
+    const SAMPLE_RATE: usize = 16000;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                amplitude
+                    * (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    fn chunk(samples: &[f32]) -> DataChunk {
+        DataChunk::Real(
+            GenericDataChunk::from_flat_sata(samples, AudioMetadata::new(1, SAMPLE_RATE)).unwrap(),
+        )
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    // the carrier comes through only in the bands where the modulator has energy
+    #[test]
+    fn carrier_follows_modulator_bands() {
+        let len = 8000;
+        let mut seed = 1u32;
+        let noise = noise(&mut seed, 1.0, len);
+        let run = |modulator: &[f32]| {
+            let mut vocoder = ChannelVocoderNode::new();
+            let mut output = vec![];
+            for (m, c) in modulator.chunks(500).zip(noise.chunks(500)) {
+                match vocoder.process_chunk(chunk(m), Some(chunk(c))) {
+                    Some(DataChunk::Real(chunk)) => output.extend_from_slice(chunk.samples(0)),
+                    _ => panic!(),
+                }
+            }
+            output
+        };
+
+        let output = run(&sine(500.0, 0.5, len));
+        assert!(rms(&output[4000..]) > 0.1, "{}", rms(&output[4000..]));
+        // the output spectrum is concentrated around the modulator
+        let power = |frequency: f32| magnitude(&output[4000..], frequency, SAMPLE_RATE).powi(2);
+        let near = (480..520).step_by(5).map(|f| power(f as f32)).sum::<f32>();
+        let far = (2980..3020)
+            .step_by(5)
+            .map(|f| power(f as f32))
+            .sum::<f32>();
+        assert!(near > far * 100.0, "{} {}", near, far);
+
+        // a silent modulator silences the carrier
+        let output = run(&vec![0.0; len]);
+        assert!(rms(&output) < 1e-3);
+    }
+
+    #[test]
+    fn carrier_is_rechunked_to_the_modulator() {
+        let len = 4000;
+        let modulator = sine(500.0, 0.5, len);
+        let carrier = sine(520.0, 0.5, len);
+        let mut aligned = ChannelVocoderNode::new();
+        let expected = modulator
+            .chunks(500)
+            .zip(carrier.chunks(500))
+            .flat_map(
+                |(m, c)| match aligned.process_chunk(chunk(m), Some(chunk(c))) {
+                    Some(DataChunk::Real(chunk)) => chunk.samples(0).to_vec(),
+                    _ => panic!(),
+                },
+            )
+            .collect::<Vec<_>>();
+
+        let mut vocoder = ChannelVocoderNode::new();
+        let (modulator_tx, modulator_rx) = std::sync::mpsc::sync_channel(16);
+        let (carrier_tx, carrier_rx) = std::sync::mpsc::sync_channel(16);
+        let (out_tx, out_rx) = std::sync::mpsc::sync_channel(16);
+        vocoder.add_input().unwrap().rx = Some(modulator_rx);
+        vocoder.add_input().unwrap().rx = Some(carrier_rx);
+        vocoder.add_output().unwrap().tx = Some(out_tx);
+        for m in modulator.chunks(500) {
+            modulator_tx.send(chunk(m)).unwrap();
+        }
+        // the modulator waits for a carrier which arrives late and in other lengths
+        vocoder.run_once();
+        assert!(out_rx.try_recv().is_err());
+        for c in carrier.chunks(300) {
+            carrier_tx.send(chunk(c)).unwrap();
+        }
+        vocoder.run_once();
+        let mut output = vec![];
+        while let Ok(DataChunk::Real(chunk)) = out_rx.try_recv() {
+            output.extend_from_slice(chunk.samples(0));
+        }
+        assert_eq!(output.len(), len);
+        assert!(rms(&output) > 0.01);
+        for (a, b) in output.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+}
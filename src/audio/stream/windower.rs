@@ -80,7 +80,7 @@ impl Windower {
             )
             .unwrap();
             windowed_chunk.set_window_info(Some(WindowInfo::new(
-                self.window_function.clone(),
+                self.window_function,
                 self.delay,
             )));
             for (c, b) in self.buffer.iter().enumerate() {
@@ -114,6 +114,9 @@ impl NodeTrait for Windower {
     fn id(&self) -> NodeId {
         self.id
     }
+    fn set_id(&mut self, id: NodeId) {
+        self.id = id;
+    }
     fn run_once(&mut self) {
         if self.inputs().len() != 1 {
             return;
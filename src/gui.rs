@@ -1,3 +1,4 @@
+use glium::glutin::event::VirtualKeyCode;
 use imgui::*;
 use serde_json;
 use std::io::{self, Read};
@@ -48,6 +49,7 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
     let g = node_editor_state.graph();
     g.lock().unwrap().remove_unregistered_ports();
     g.lock().unwrap().connect_port_channels();
+    node_editor_state.remove_stale_entries();
 
     let (input_monitor_rx, output_monitor_rx) = {
         let g = g.lock().unwrap();
@@ -92,16 +94,67 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
         let output_rx = output_monitor_rx;
         let mut input_amplitudes = vec![];
         let mut output_amplitudes = vec![];
+        let mut save_template_opened = false;
+        let mut template_name = ImString::with_capacity(64);
+        let mut template_error: Option<String> = None;
+        let mut import_project_opened = false;
+        let mut project_path = ImString::with_capacity(256);
+        let mut history = History::new();
+        let mut move_origin = node_editor_state.node_positions().clone();
+        // imgui has no key index for D, so presses are found by comparing with the last frame
+        let mut d_was_down = false;
         system.main_loop(move |_, ui| {
             ui.set_mouse_cursor(Some(MouseCursor::Arrow));
             let current_input_device_name = host.current_input_device_name();
             let current_output_device_name = host.current_output_device_name();
+            let mut copy_requested = false;
+            let mut paste_requested = false;
+            let mut duplicate_requested = false;
+            let mut frame_all_requested = false;
             ui.main_menu_bar(|| {
                 ui.menu(im_str!("File"), true, || {
                     if MenuItem::new(im_str!("Save")).build(&ui) {
                         println!("{}", serde_json::to_string(&node_editor_state).unwrap());
                     }
                 });
+                ui.menu(im_str!("Edit"), true, || {
+                    if MenuItem::new(im_str!("Undo"))
+                        .shortcut(im_str!("Ctrl+Z"))
+                        .enabled(history.can_undo())
+                        .build(&ui)
+                    {
+                        if let Err(e) = history.undo(&mut node_editor_state) {
+                            eprintln!("{}", e);
+                        }
+                    }
+                    if MenuItem::new(im_str!("Redo"))
+                        .shortcut(im_str!("Ctrl+Shift+Z"))
+                        .enabled(history.can_redo())
+                        .build(&ui)
+                    {
+                        if let Err(e) = history.redo(&mut node_editor_state) {
+                            eprintln!("{}", e);
+                        }
+                    }
+                    ui.separator();
+                    let has_selection = !node_editor_state.selected().is_empty();
+                    copy_requested |= MenuItem::new(im_str!("Copy"))
+                        .shortcut(im_str!("Ctrl+C"))
+                        .enabled(has_selection)
+                        .build(&ui);
+                    paste_requested |= MenuItem::new(im_str!("Paste"))
+                        .shortcut(im_str!("Ctrl+V"))
+                        .build(&ui);
+                    duplicate_requested |= MenuItem::new(im_str!("Duplicate"))
+                        .shortcut(im_str!("Ctrl+D"))
+                        .enabled(has_selection)
+                        .build(&ui);
+                });
+                ui.menu(im_str!("View"), true, || {
+                    frame_all_requested |= MenuItem::new(im_str!("Frame All"))
+                        .shortcut(im_str!("Home"))
+                        .build(&ui);
+                });
                 ui.menu(im_str!("Devices"), true, || {
                     ui.menu(im_str!("Input"), true, || {
                         for name in host.input_device_names().iter() {
@@ -137,11 +190,20 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
                             if MenuItem::new(im_str!($name)).build(&ui) {
                                 let mut node = $node;
                                 let node_id = node.id();
-                                let mut g = g.lock().unwrap();
-                                g.add(node);
-                                g.add_input(&node_id).unwrap();
-                                g.add_output(&node_id).unwrap();
+                                let is_source = node.is_source();
+                                {
+                                    let mut g = g.lock().unwrap();
+                                    g.add(node);
+                                    if !is_source {
+                                        g.add_input(&node_id).unwrap();
+                                    }
+                                    g.add_output(&node_id).unwrap();
+                                }
                                 node_editor_state.set_node_pos(node_id, default_pos);
+                                match NodeSnapshot::capture(&node_editor_state, &[node_id]) {
+                                    Ok(s) => history.push(Command::InsertNodes(s)),
+                                    Err(e) => eprintln!("{}", e),
+                                }
                             }
                         };
                     }
@@ -167,7 +229,8 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
                         "Filter",
                         Node::Filter(FilterNode::new(FilterOperation::ReplaceLowerAmplitudesFd {
                             value: 0.0,
-                            threshold: 100.0
+                            threshold: 100.0,
+                            transition: 50.0
                         }))
                     );
                     make_node_menu!(
@@ -183,8 +246,116 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
                         "Formant Shifter",
                         Node::FormantShifter(FormantShifter::new())
                     );
+                    make_node_menu!(
+                        "Pitch Tracker",
+                        Node::PitchTracker(PitchTrackerNode::new())
+                    );
+                    make_node_menu!("Harmonizer", Node::Harmonizer(HarmonizerNode::new()));
+                    make_node_menu!("Time Stretch", Node::TimeStretch(TimeStretchNode::new(1.0)));
+                    make_node_menu!("Equalizer", Node::Equalizer(EqualizerNode::new()));
+                    make_node_menu!("Noise Gate", Node::NoiseGate(NoiseGateNode::new()));
+                    make_node_menu!("Compressor", Node::Compressor(CompressorNode::new()));
+                    make_node_menu!("Limiter", Node::Limiter(LimiterNode::new()));
+                    make_node_menu!(
+                        "Noise Reduction",
+                        Node::NoiseReduction(NoiseReductionNode::new())
+                    );
+                    make_node_menu!("Voice Activity", Node::Vad(VadNode::new()));
+                    make_node_menu!(
+                        "Channel Vocoder",
+                        Node::ChannelVocoder(ChannelVocoderNode::new())
+                    );
+                    make_node_menu!(
+                        "Oscillator",
+                        Node::Oscillator(OscillatorNode::new(Waveform::Sine))
+                    );
+                    make_node_menu!("Noise", Node::Noise(NoiseNode::new(NoiseColor::White)));
+                    make_node_menu!(
+                        "Ring Modulator",
+                        Node::RingModulator(RingModulatorNode::new())
+                    );
+                    make_node_menu!(
+                        "Frequency Shifter",
+                        Node::FrequencyShifter(FrequencyShifterNode::new())
+                    );
+                });
+                ui.menu(im_str!("Templates"), true, || {
+                    if MenuItem::new(im_str!("Save Selection..."))
+                        .enabled(!node_editor_state.selected().is_empty())
+                        .build(&ui)
+                    {
+                        save_template_opened = true;
+                    }
+                    ui.menu(im_str!("Insert"), true, || {
+                        for name in NodeTemplate::library_names().iter() {
+                            if MenuItem::new(&im_str!("{}", name)).build(&ui) {
+                                match NodeTemplate::load(name)
+                                    .and_then(|t| {
+                                        t.instantiate(&mut node_editor_state, [100.0, 100.0])
+                                    })
+                                    .and_then(|ids| NodeSnapshot::capture(&node_editor_state, &ids))
+                                {
+                                    Ok(s) => history.push(Command::InsertNodes(s)),
+                                    Err(e) => eprintln!("{}", e),
+                                }
+                            }
+                        }
+                    });
+                    if MenuItem::new(im_str!("Import Project...")).build(&ui) {
+                        import_project_opened = true;
+                    }
                 });
             });
+            if save_template_opened {
+                let mut saved = false;
+                Window::new(im_str!("Save Template"))
+                    .opened(&mut save_template_opened)
+                    .always_auto_resize(true)
+                    .build(&ui, || {
+                        ui.input_text(im_str!("Name"), &mut template_name).build();
+                        if ui.small_button(im_str!("Save")) {
+                            match NodeTemplate::from_selection(&node_editor_state)
+                                .and_then(|t| t.save(template_name.to_str()))
+                            {
+                                Ok(_) => saved = true,
+                                Err(e) => template_error = Some(e.to_string()),
+                            }
+                        }
+                        if let Some(e) = template_error.as_ref() {
+                            ui.text_colored([1.0, 0.3, 0.3, 1.0], e);
+                        }
+                    });
+                if saved || !save_template_opened {
+                    save_template_opened = false;
+                    template_error = None;
+                }
+            }
+            if import_project_opened {
+                let mut imported = false;
+                Window::new(im_str!("Import Project"))
+                    .opened(&mut import_project_opened)
+                    .always_auto_resize(true)
+                    .build(&ui, || {
+                        ui.input_text(im_str!("Path"), &mut project_path).build();
+                        if ui.small_button(im_str!("Import")) {
+                            match NodeTemplate::import_project(project_path.to_str())
+                                .and_then(|t| {
+                                    t.instantiate(&mut node_editor_state, [100.0, 100.0])
+                                })
+                                .and_then(|ids| NodeSnapshot::capture(&node_editor_state, &ids))
+                            {
+                                Ok(s) => {
+                                    history.push(Command::InsertNodes(s));
+                                    imported = true;
+                                }
+                                Err(e) => eprintln!("{}", e),
+                            }
+                        }
+                    });
+                if imported {
+                    import_project_opened = false;
+                }
+            }
             Window::new(im_str!("I/O Monitor"))
                 .always_auto_resize(true)
                 .position([0.0, 20.0], Condition::FirstUseEver)
@@ -207,15 +378,84 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
                         .scale_max(1.0)
                         .graph_size([300.0, 100.0])
                         .build();
+                    // decisions of the voice activity detectors in the graph
+                    for (id, node) in g.lock().unwrap().nodes().iter() {
+                        if let Node::Vad(node) = &*node.lock().unwrap() {
+                            node.render_activity(&ui, &im_str!("##vad{:?}", id));
+                        }
+                    }
                 });
             Window::new(im_str!("Nodes"))
                 .position([400.0, 20.0], Condition::FirstUseEver)
                 .size([600.0, 600.0], Condition::FirstUseEver)
+                .scroll_bar(false)
+                .scrollable(false)
                 .build(&ui, || {
+                    ui.set_window_font_scale(node_editor_state.zoom());
+                    ui.set_cursor_pos([0.0, 0.0]);
+                    let win_pos = ui.cursor_screen_pos();
+                    let mouse_pos = ui.io().mouse_pos;
+                    node_editor_state.set_canvas_size(ui.content_region_avail());
+                    if ui.is_window_hovered() {
+                        if ui.is_mouse_down(MouseButton::Middle) {
+                            node_editor_state.pan_by(ui.io().mouse_delta);
+                        }
+                        let wheel = ui.io().mouse_wheel;
+                        if wheel != 0.0 {
+                            let zoom = node_editor_state.zoom() * 1.1f32.powf(wheel);
+                            node_editor_state.zoom_at(win_pos, mouse_pos, zoom);
+                        }
+                    }
+                    let ctrl = ui.io().key_ctrl;
+                    let shift = ui.io().key_shift;
+                    if ctrl && !ui.io().want_text_input && ui.is_key_pressed(ui.key_index(Key::Z))
+                    {
+                        let result = if shift {
+                            history.redo(&mut node_editor_state)
+                        } else {
+                            history.undo(&mut node_editor_state)
+                        };
+                        if let Err(e) = result {
+                            eprintln!("{}", e);
+                        }
+                    }
+                    // imgui-winit-support indexes `keys_down` by virtual key code
+                    let d_down = ui.io().keys_down[VirtualKeyCode::D as usize];
+                    let d_pressed = d_down && !d_was_down;
+                    d_was_down = d_down;
+                    let mut delete_request = None;
+                    if ui.is_window_focused() && !ui.io().want_text_input {
+                        frame_all_requested |= ui.is_key_pressed(ui.key_index(Key::Home));
+                        if ui.is_key_pressed(ui.key_index(Key::Delete)) {
+                            delete_request = Some(
+                                node_editor_state.selected().iter().cloned().collect::<Vec<_>>(),
+                            );
+                        }
+                        if ctrl {
+                            copy_requested |= ui.is_key_pressed(ui.key_index(Key::C));
+                            paste_requested |= ui.is_key_pressed(ui.key_index(Key::V));
+                            duplicate_requested |= d_pressed;
+                        }
+                    }
+                    let was_dragging = node_editor_state.left_dragged().is_some();
+                    if !was_dragging {
+                        move_origin = node_editor_state.node_positions().clone();
+                    }
+                    let settled = !ui.is_mouse_down(MouseButton::Left);
                     let mut connection_request = None;
                     {
-                        for (_, node) in g.lock().unwrap().nodes().iter() {
+                        for (id, node) in g.lock().unwrap().nodes().iter() {
+                            let before = if *node_editor_state.window_opened(id) {
+                                serde_json::to_string(&*node.lock().unwrap()).ok()
+                            } else {
+                                None
+                            };
                             node.lock().unwrap().render(&ui, &mut node_editor_state);
+                            if let Some(before) = before {
+                                if let Ok(after) = serde_json::to_string(&*node.lock().unwrap()) {
+                                    history.observe_node(*id, before, after, settled);
+                                }
+                            }
                             for inputs in node.lock().unwrap().inputs().iter() {
                                 connection_request = connection_request
                                     .or(inputs.render(&ui, &mut node_editor_state));
@@ -225,34 +465,136 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
                             }
                         }
                     }
+                    ui.popup(im_str!("node context menu"), || {
+                        if let Some(id) = node_editor_state.context_menu_node() {
+                            let removable = !g.lock().unwrap().is_io_node(&id);
+                            if MenuItem::new(im_str!("Duplicate"))
+                                .shortcut(im_str!("Ctrl+D"))
+                                .enabled(removable)
+                                .build(&ui)
+                            {
+                                if !node_editor_state.is_selected(&id) {
+                                    node_editor_state.clear_selection();
+                                    node_editor_state.select(id);
+                                }
+                                duplicate_requested = true;
+                            }
+                            if MenuItem::new(im_str!("Delete"))
+                                .shortcut(im_str!("Delete"))
+                                .enabled(removable)
+                                .build(&ui)
+                            {
+                                delete_request = Some(if node_editor_state.is_selected(&id) {
+                                    node_editor_state.selected().iter().cloned().collect()
+                                } else {
+                                    vec![id]
+                                });
+                            }
+                        }
+                    });
+                    if let Some(ids) = delete_request {
+                        // the Input and Output nodes are never part of a snapshot
+                        match NodeSnapshot::capture(&node_editor_state, &ids) {
+                            Ok(s) if !s.ids().is_empty() => {
+                                if let Err(e) =
+                                    history.perform(&mut node_editor_state, Command::RemoveNodes(s))
+                                {
+                                    eprintln!("{}", e);
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(e) => eprintln!("{}", e),
+                        }
+                    }
+                    if was_dragging && node_editor_state.left_dragged().is_none() {
+                        history.observe_moves(&move_origin, node_editor_state.node_positions());
+                    }
                     if let Some(request) = connection_request {
-                        let mut g = g.lock().unwrap();
-                        if g.is_output_port(&request.0) && g.is_input_port(&request.1) {
-                            let _ = g.connect_ports(&request.0, &request.1);
+                        let valid = {
+                            let g = g.lock().unwrap();
+                            g.is_output_port(&request.0)
+                                && g.is_input_port(&request.1)
+                                && !g.sends_control_to_output(&request.0, &request.1)
+                        };
+                        if valid {
+                            let command =
+                                Command::connect_ports(&node_editor_state, request.0, request.1);
+                            let _ = history.perform(&mut node_editor_state, command);
                         }
                     }
+                    if frame_all_requested {
+                        node_editor_state.frame_all();
+                    }
                     let draw_list = ui.get_window_draw_list();
-                    ui.set_cursor_pos([0.0, 0.0]);
-                    let win_pos = ui.cursor_screen_pos();
+                    let can_hover_edge = ui.is_window_hovered()
+                        && !ui.is_any_item_hovered()
+                        && node_editor_state.right_dragged().is_none();
+                    let mut hovered_edge = None;
                     for (start, end) in g.lock().unwrap().edges().iter() {
                         let start_pos = node_editor_state.output_pos(start).unwrap();
-                        let start_pos = [start_pos[0] + win_pos[0], start_pos[1] + win_pos[1]];
+                        let start_pos = node_editor_state.to_screen(win_pos, *start_pos);
                         let end_pos = node_editor_state.input_pos(end).unwrap();
-                        let end_pos = [end_pos[0] + win_pos[0], end_pos[1] + win_pos[1]];
+                        let end_pos = node_editor_state.to_screen(win_pos, *end_pos);
+                        let points = [
+                            [start_pos[0], start_pos[1] + 5.0],
+                            [start_pos[0], (start_pos[1] + end_pos[1]) / 2.0],
+                            [end_pos[0], (start_pos[1] + end_pos[1]) / 2.0],
+                            end_pos.clone(),
+                        ];
+                        let hovered = can_hover_edge
+                            && hovered_edge.is_none()
+                            && bezier_distance(points, mouse_pos) < 4.0;
+                        if hovered {
+                            hovered_edge = Some((*start, *end));
+                        }
                         draw_list
                             .add_bezier_curve(
-                                [start_pos[0], start_pos[1] + 5.0],
-                                [start_pos[0], (start_pos[1] + end_pos[1]) / 2.0],
-                                [end_pos[0], (start_pos[1] + end_pos[1]) / 2.0],
-                                end_pos.clone(),
-                                (0.5, 0.5, 0.5, 0.5),
+                                points[0],
+                                points[1],
+                                points[2],
+                                points[3],
+                                if hovered {
+                                    (1.0, 0.6, 0.3, 0.9)
+                                } else {
+                                    (0.5, 0.5, 0.5, 0.5)
+                                },
                             )
                             .thickness(2.0)
                             .build();
                     }
+                    if let Some((from, to)) = hovered_edge {
+                        if ui.is_mouse_clicked(MouseButton::Left) {
+                            let command = Command::DisconnectPorts { from, to };
+                            if let Err(e) = history.perform(&mut node_editor_state, command) {
+                                eprintln!("{}", e);
+                            }
+                        }
+                    } else if can_hover_edge && ui.is_mouse_clicked(MouseButton::Left) {
+                        if !ctrl {
+                            node_editor_state.clear_selection();
+                        }
+                        let origin = node_editor_state.to_canvas(win_pos, mouse_pos);
+                        node_editor_state.set_box_select_origin(Some(origin));
+                    }
+                    if let Some(origin) = node_editor_state.box_select_origin() {
+                        let corner = node_editor_state.to_canvas(win_pos, mouse_pos);
+                        if ui.is_mouse_down(MouseButton::Left) {
+                            let origin = node_editor_state.to_screen(win_pos, origin);
+                            draw_list
+                                .add_rect(origin, mouse_pos, (0.5, 0.6, 1.0, 0.2))
+                                .filled(true)
+                                .build();
+                            draw_list
+                                .add_rect(origin, mouse_pos, (0.5, 0.6, 1.0, 0.8))
+                                .build();
+                        } else {
+                            node_editor_state.select_in_rect(origin, corner);
+                            node_editor_state.set_box_select_origin(None);
+                        }
+                    }
                     if let Some(start) = node_editor_state.right_dragged() {
                         let start_pos = node_editor_state.output_pos(&start).unwrap();
-                        let start_pos = [start_pos[0] + win_pos[0], start_pos[1] + win_pos[1]];
+                        let start_pos = node_editor_state.to_screen(win_pos, *start_pos);
                         let end_pos = ui.io().mouse_pos;
                         draw_list
                             .add_bezier_curve(
@@ -266,6 +608,42 @@ pub fn main_loop(host: audio::Host, input: Receiver<DataChunk>, output: SyncSend
                             .build();
                     }
                 });
+            let pasted = if copy_requested || duplicate_requested {
+                match NodeTemplate::from_selection(&node_editor_state) {
+                    Ok(t) if t.is_empty() => None,
+                    Ok(t) => {
+                        if copy_requested {
+                            if let Ok(json) = t.to_json() {
+                                ui.set_clipboard_text(&ImString::new(json));
+                            }
+                        }
+                        if duplicate_requested {
+                            Some(Ok(t))
+                        } else {
+                            None
+                        }
+                    }
+                    Err(e) => Some(Err(e)),
+                }
+            } else if paste_requested {
+                ui.clipboard_text()
+                    .map(|json| NodeTemplate::from_json(json.to_str()))
+            } else {
+                None
+            };
+            if let Some(template) = pasted {
+                match template
+                    .and_then(|t| {
+                        let origin = t.origin();
+                        t.instantiate(&mut node_editor_state, [origin[0] + 20.0, origin[1] + 20.0])
+                    })
+                    .and_then(|ids| NodeSnapshot::capture(&node_editor_state, &ids))
+                {
+                    Ok(s) if !s.ids().is_empty() => history.push(Command::InsertNodes(s)),
+                    Ok(_) => {}
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
         });
     }
 }
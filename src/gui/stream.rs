@@ -1,39 +1,86 @@
 pub mod aggregate;
 pub mod arithmetic;
+pub mod compressor;
 pub mod dewindower;
+pub mod equalizer;
 pub mod filter;
 pub mod formantshifter;
+pub mod frequencyshifter;
 pub mod ft;
+pub mod gate;
+pub mod harmonizer;
+pub mod history;
 pub mod identity;
+pub mod limiter;
 pub mod node;
+pub mod noise;
+pub mod noisereduction;
+pub mod oscillator;
 pub mod phasevocoder;
+pub mod pitchtracker;
 pub mod port;
 pub mod psola;
 pub mod replicator;
+pub mod ringmodulator;
+pub mod template;
+pub mod timestretch;
+pub mod vad;
+pub mod vocoder;
 pub mod windower;
 pub use aggregate::*;
 pub use arithmetic::*;
+pub use compressor::*;
 pub use dewindower::*;
+pub use equalizer::*;
 pub use filter::*;
 pub use formantshifter::*;
+pub use frequencyshifter::*;
 pub use ft::*;
+pub use gate::*;
+pub use harmonizer::*;
+pub use history::*;
 pub use identity::*;
+pub use limiter::*;
 pub use node::*;
+pub use noise::*;
+pub use noisereduction::*;
+pub use oscillator::*;
 pub use phasevocoder::*;
+pub use pitchtracker::*;
 pub use port::*;
 pub use psola::*;
 pub use replicator::*;
+pub use ringmodulator::*;
+pub use template::*;
+pub use timestretch::*;
+pub use vad::*;
+pub use vocoder::*;
 pub use windower::*;
 
 use crate::audio::stream::graph::Graph;
 use crate::audio::stream::node::*;
 use imgui::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
 pub type ConnectRequest = (OutputPortId, InputPortId);
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Viewport {
+    pan: [f32; 2],
+    zoom: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            pan: [0.0, 0.0],
+            zoom: 1.0,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NodeEditorState {
     graph: Arc<Mutex<Graph>>,
@@ -43,6 +90,18 @@ pub struct NodeEditorState {
     left_dragged: Option<NodeId>,
     right_dragged: Option<OutputPortId>,
     window_opened: HashMap<NodeId, bool>,
+    #[serde(default)]
+    selected: HashSet<NodeId>,
+    #[serde(skip)]
+    context_menu_node: Option<NodeId>,
+    #[serde(default)]
+    viewport: Viewport,
+    #[serde(skip)]
+    node_size: HashMap<NodeId, [f32; 2]>,
+    #[serde(skip)]
+    canvas_size: [f32; 2],
+    #[serde(skip)]
+    box_select_origin: Option<[f32; 2]>,
 }
 
 impl NodeEditorState {
@@ -55,6 +114,12 @@ impl NodeEditorState {
             left_dragged: None,
             right_dragged: None,
             window_opened: HashMap::new(),
+            selected: HashSet::new(),
+            context_menu_node: None,
+            viewport: Viewport::default(),
+            node_size: HashMap::new(),
+            canvas_size: [0.0, 0.0],
+            box_select_origin: None,
         }
     }
 
@@ -86,6 +151,10 @@ impl NodeEditorState {
         self.output_pos.get(uuid)
     }
 
+    pub fn node_positions(&self) -> &HashMap<NodeId, [f32; 2]> {
+        &self.node_pos
+    }
+
     pub fn node_pos_mut(&mut self, uuid: &NodeId) -> Option<&mut [f32; 2]> {
         self.node_pos.get_mut(uuid)
     }
@@ -119,4 +188,185 @@ impl NodeEditorState {
         }
         self.window_opened.get_mut(id).unwrap()
     }
+
+    pub fn selected(&self) -> &HashSet<NodeId> {
+        &self.selected
+    }
+
+    pub fn is_selected(&self, id: &NodeId) -> bool {
+        self.selected.contains(id)
+    }
+
+    pub fn select(&mut self, id: NodeId) {
+        self.selected.insert(id);
+    }
+
+    pub fn deselect(&mut self, id: &NodeId) {
+        self.selected.remove(id);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selected.clear();
+    }
+
+    pub fn set_context_menu_node(&mut self, id: Option<NodeId>) {
+        self.context_menu_node = id;
+    }
+
+    pub fn context_menu_node(&self) -> Option<NodeId> {
+        self.context_menu_node
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.viewport.zoom
+    }
+
+    // node and port positions are kept in canvas coordinates, which are mapped onto the
+    // window through the viewport
+    pub fn to_screen(&self, win_pos: [f32; 2], pos: [f32; 2]) -> [f32; 2] {
+        let Viewport { pan, zoom } = self.viewport;
+        [
+            win_pos[0] + pos[0] * zoom + pan[0],
+            win_pos[1] + pos[1] * zoom + pan[1],
+        ]
+    }
+
+    pub fn to_canvas(&self, win_pos: [f32; 2], screen_pos: [f32; 2]) -> [f32; 2] {
+        let Viewport { pan, zoom } = self.viewport;
+        [
+            (screen_pos[0] - win_pos[0] - pan[0]) / zoom,
+            (screen_pos[1] - win_pos[1] - pan[1]) / zoom,
+        ]
+    }
+
+    pub fn pan_by(&mut self, delta: [f32; 2]) {
+        self.viewport.pan[0] += delta[0];
+        self.viewport.pan[1] += delta[1];
+    }
+
+    // changes the zoom while keeping the canvas point under `screen_pos` in place
+    pub fn zoom_at(&mut self, win_pos: [f32; 2], screen_pos: [f32; 2], zoom: f32) {
+        let zoom = zoom.max(0.25).min(4.0);
+        let pos = self.to_canvas(win_pos, screen_pos);
+        self.viewport.zoom = zoom;
+        self.viewport.pan = [
+            screen_pos[0] - win_pos[0] - pos[0] * zoom,
+            screen_pos[1] - win_pos[1] - pos[1] * zoom,
+        ];
+    }
+
+    pub fn set_canvas_size(&mut self, size: [f32; 2]) {
+        self.canvas_size = size;
+    }
+
+    pub fn set_node_size(&mut self, id: NodeId, size: [f32; 2]) {
+        self.node_size.insert(id, size);
+    }
+
+    pub fn node_size(&self, id: &NodeId) -> [f32; 2] {
+        self.node_size.get(id).cloned().unwrap_or([0.0, 0.0])
+    }
+
+    pub fn frame_all(&mut self) {
+        if self.node_pos.is_empty() || self.canvas_size[0] <= 0.0 || self.canvas_size[1] <= 0.0 {
+            return;
+        }
+        let mut min = [f32::MAX; 2];
+        let mut max = [f32::MIN; 2];
+        for (id, pos) in self.node_pos.iter() {
+            let size = self.node_size(id);
+            for k in 0..2 {
+                min[k] = min[k].min(pos[k]);
+                max[k] = max[k].max(pos[k] + size[k]);
+            }
+        }
+        let margin = 40.0;
+        let zoom = ((self.canvas_size[0] - 2.0 * margin) / (max[0] - min[0]).max(1.0))
+            .min((self.canvas_size[1] - 2.0 * margin) / (max[1] - min[1]).max(1.0))
+            .max(0.25)
+            .min(4.0);
+        self.viewport.zoom = zoom;
+        self.viewport.pan = [
+            self.canvas_size[0] / 2.0 - (min[0] + max[0]) / 2.0 * zoom,
+            self.canvas_size[1] / 2.0 - (min[1] + max[1]) / 2.0 * zoom,
+        ];
+    }
+
+    pub fn move_node(&mut self, id: &NodeId, delta: [f32; 2]) {
+        if let Some(pos) = self.node_pos.get_mut(id) {
+            pos[0] += delta[0];
+            pos[1] += delta[1];
+        }
+    }
+
+    pub fn move_selected(&mut self, delta: [f32; 2]) {
+        let ids = self.selected.iter().cloned().collect::<Vec<_>>();
+        for id in ids.iter() {
+            self.move_node(id, delta);
+        }
+    }
+
+    pub fn set_box_select_origin(&mut self, pos: Option<[f32; 2]>) {
+        self.box_select_origin = pos;
+    }
+
+    pub fn box_select_origin(&self) -> Option<[f32; 2]> {
+        self.box_select_origin
+    }
+
+    // selects every node overlapping the rectangle spanned by two canvas positions
+    pub fn select_in_rect(&mut self, a: [f32; 2], b: [f32; 2]) {
+        let min = [a[0].min(b[0]), a[1].min(b[1])];
+        let max = [a[0].max(b[0]), a[1].max(b[1])];
+        let ids = self
+            .node_pos
+            .iter()
+            .filter(|(id, pos)| {
+                let size = self.node_size(id);
+                pos[0] <= max[0]
+                    && min[0] <= pos[0] + size[0]
+                    && pos[1] <= max[1]
+                    && min[1] <= pos[1] + size[1]
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in ids.into_iter() {
+            self.select(id);
+        }
+    }
+
+    // forgets positions and flags of nodes and ports which are no longer in the graph
+    pub fn remove_stale_entries(&mut self) {
+        let g = self.graph.clone();
+        let g = g.lock().unwrap();
+        self.node_pos.retain(|id, _| g.node(id).is_ok());
+        self.node_size.retain(|id, _| g.node(id).is_ok());
+        self.window_opened.retain(|id, _| g.node(id).is_ok());
+        self.selected.retain(|id| g.node(id).is_ok());
+        self.input_pos.retain(|id, _| g.is_input_port(id));
+        self.output_pos.retain(|id, _| g.is_output_port(id));
+        if self.left_dragged.map(|id| g.node(&id).is_err()).unwrap_or(false) {
+            self.left_dragged = None;
+        }
+        if self.right_dragged.map(|id| !g.is_output_port(&id)).unwrap_or(false) {
+            self.right_dragged = None;
+        }
+        if self.context_menu_node.map(|id| g.node(&id).is_err()).unwrap_or(false) {
+            self.context_menu_node = None;
+        }
+    }
+}
+
+pub fn bezier_distance(points: [[f32; 2]; 4], pos: [f32; 2]) -> f32 {
+    let segments = 32;
+    (0..=segments)
+        .map(|i| {
+            let t = i as f32 / segments as f32;
+            let s = 1.0 - t;
+            let coefs = [s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t];
+            let x = (0..4).map(|k| coefs[k] * points[k][0]).sum::<f32>();
+            let y = (0..4).map(|k| coefs[k] * points[k][1]).sum::<f32>();
+            ((x - pos[0]).powi(2) + (y - pos[1]).powi(2)).sqrt()
+        })
+        .fold(f32::MAX, f32::min)
 }
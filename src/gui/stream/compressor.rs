@@ -0,0 +1,82 @@
+use super::*;
+use crate::audio::stream::{compressor::CompressorNode, node::NodeTrait};
+use imgui::*;
+
+impl InputHandler for CompressorNode {}
+
+impl CompressorNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Compressor".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Compressor {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                let overlay = match self.gain_history().last() {
+                    Some(g) => format!("{:.1} dB", g),
+                    None => "".to_string(),
+                };
+                ui.plot_lines(im_str!("gain reduction"), self.gain_history())
+                    .overlay_text(&ImString::new(overlay))
+                    .scale_min(-40.0)
+                    .scale_max(0.0)
+                    .graph_size([400.0, 100.0])
+                    .build();
+                // input level from -60 dB to 0 dB
+                let curve = (0..=120)
+                    .map(|i| self.output_level(i as f32 / 2.0 - 60.0))
+                    .collect::<Vec<_>>();
+                ui.plot_lines(im_str!("transfer curve"), &curve)
+                    .scale_min(-60.0)
+                    .scale_max(0.0)
+                    .graph_size([400.0, 100.0])
+                    .build();
+                Slider::new(
+                    im_str!("threshold (dB)"),
+                    std::ops::RangeInclusive::new(-60.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.threshold_mut());
+                Slider::new(im_str!("ratio"), std::ops::RangeInclusive::new(1.0, 20.0))
+                    .display_format(im_str!("%0.1f"))
+                    .build(ui, self.ratio_mut());
+                Slider::new(
+                    im_str!("knee (dB)"),
+                    std::ops::RangeInclusive::new(0.0, 24.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.knee_mut());
+                Slider::new(
+                    im_str!("attack (ms)"),
+                    std::ops::RangeInclusive::new(0.0, 200.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.attack_mut());
+                Slider::new(
+                    im_str!("release (ms)"),
+                    std::ops::RangeInclusive::new(1.0, 2000.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.release_mut());
+                Slider::new(
+                    im_str!("makeup (dB)"),
+                    std::ops::RangeInclusive::new(0.0, 40.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.makeup_mut());
+            });
+    }
+}
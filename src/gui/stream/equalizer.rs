@@ -0,0 +1,105 @@
+use super::*;
+use crate::audio::stream::{
+    equalizer::{BandType, EqBand, EqualizerNode},
+    node::NodeTrait,
+};
+use imgui::*;
+
+const RESPONSE_POINTS: usize = 200;
+const RESPONSE_RANGE_DB: f32 = 24.0;
+
+impl InputHandler for EqualizerNode {}
+
+impl EqualizerNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Equalizer".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Equalizer {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                // logarithmic frequency axis from 20 Hz to Nyquist
+                let nyquist = self.sample_rate() as f32 / 2.0;
+                let last = (RESPONSE_POINTS - 1) as f32;
+                let frequencies = (0..RESPONSE_POINTS)
+                    .map(|i| 20.0 * (nyquist / 20.0).powf(i as f32 / last))
+                    .collect::<Vec<_>>();
+                ui.plot_lines(im_str!("response"), &self.response_db(&frequencies))
+                    .overlay_text(&im_str!("20 Hz - {:.0} Hz", nyquist))
+                    .scale_min(-RESPONSE_RANGE_DB)
+                    .scale_max(RESPONSE_RANGE_DB)
+                    .graph_size([400.0, 150.0])
+                    .build();
+
+                let mut removed = None;
+                for (i, band) in self.bands_mut().iter_mut().enumerate() {
+                    ui.separator();
+                    ui.text(format!("Band {}", i + 1));
+                    ui.same_line(0.0);
+                    if ui.small_button(&im_str!("Remove##{}", i)) {
+                        removed = Some(i);
+                    }
+                    let types = [
+                        ("Low shelf", BandType::LowShelf),
+                        ("High shelf", BandType::HighShelf),
+                        ("Peaking", BandType::Peaking),
+                        ("Low pass", BandType::LowPass),
+                        ("High pass", BandType::HighPass),
+                        ("Notch", BandType::Notch),
+                        ("Band pass", BandType::BandPass),
+                    ];
+                    for (j, (name, band_type)) in types.iter().enumerate() {
+                        if j > 0 {
+                            ui.same_line(0.0);
+                        }
+                        ui.radio_button(
+                            &im_str!("{}##{}", name, i),
+                            &mut band.band_type,
+                            *band_type,
+                        );
+                    }
+                    Slider::new(
+                        &im_str!("frequency (Hz)##{}", i),
+                        std::ops::RangeInclusive::new(20.0, 20000.0),
+                    )
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, &mut band.frequency);
+                    if band.band_type.has_gain() {
+                        Slider::new(
+                            &im_str!("gain (dB)##{}", i),
+                            std::ops::RangeInclusive::new(-RESPONSE_RANGE_DB, RESPONSE_RANGE_DB),
+                        )
+                        .display_format(im_str!("%0.1f"))
+                        .build(ui, &mut band.gain);
+                    }
+                    Slider::new(
+                        &im_str!("Q##{}", i),
+                        std::ops::RangeInclusive::new(0.1, 10.0),
+                    )
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, &mut band.q);
+                }
+                if let Some(i) = removed {
+                    self.bands_mut().remove(i);
+                }
+                ui.separator();
+                if ui.small_button(im_str!("Add band")) {
+                    let band = EqBand::new(BandType::Peaking, 1000.0);
+                    self.bands_mut().push(band);
+                }
+            });
+    }
+}
@@ -3,9 +3,11 @@ use crate::audio::stream::{filter::*, node::NodeTrait};
 use imgui::*;
 
 #[derive(PartialEq, Copy, Clone, Debug)]
-enum OpHighLow {
+enum OpRegion {
     High,
     Low,
+    BandPass,
+    BandStop,
 }
 #[derive(PartialEq, Copy, Clone, Debug)]
 enum OpDomain {
@@ -37,54 +39,135 @@ impl FilterNode {
             .position(mouse_pos, Condition::Once)
             .build(&ui, || {
                 use FilterOperation::*;
-                let mut op_hl = match self.op() {
-                    ReplaceLowerAmplitudesFd { value, threshold } => OpHighLow::Low,
-                    ReplaceLowerAmplitudesTd { value, threshold } => OpHighLow::Low,
-                    _ => OpHighLow::High,
+                let mut op_region = match self.op() {
+                    ReplaceLowerAmplitudesFd { .. } | ReplaceLowerAmplitudesTd { .. } => {
+                        OpRegion::Low
+                    }
+                    ReplaceHigherAmplitudesFd { .. } | ReplaceHigherAmplitudesTd { .. } => {
+                        OpRegion::High
+                    }
+                    BandPassFd { .. } => OpRegion::BandPass,
+                    BandStopFd { .. } => OpRegion::BandStop,
                 };
-                let mut op_d = match self.op() {
-                    ReplaceLowerAmplitudesFd { value, threshold } => OpDomain::Freq,
-                    ReplaceHigherAmplitudesFd { value, threshold } => OpDomain::Freq,
-                    _ => OpDomain::Time,
+                let mut op_d = if self.op().is_frequency_domain() {
+                    OpDomain::Freq
+                } else {
+                    OpDomain::Time
                 };
-                let (mut threshold, mut value) = match self.op() {
-                    ReplaceHigherAmplitudesFd { threshold, value } => (*threshold, *value),
-                    ReplaceLowerAmplitudesFd { threshold, value } => (*threshold, *value),
-                    ReplaceHigherAmplitudesTd { threshold, value } => (*threshold as f32, *value),
-                    ReplaceLowerAmplitudesTd { threshold, value } => (*threshold as f32, *value),
+                let (mut low, mut high, mut transition, mut value) = match *self.op() {
+                    ReplaceHigherAmplitudesFd {
+                        threshold,
+                        value,
+                        transition,
+                    }
+                    | ReplaceLowerAmplitudesFd {
+                        threshold,
+                        value,
+                        transition,
+                    } => (threshold, threshold * 2.0, transition, value),
+                    ReplaceHigherAmplitudesTd {
+                        threshold,
+                        value,
+                        transition,
+                    }
+                    | ReplaceLowerAmplitudesTd {
+                        threshold,
+                        value,
+                        transition,
+                    } => (
+                        threshold as f32,
+                        threshold as f32 * 2.0,
+                        transition as f32,
+                        value,
+                    ),
+                    BandPassFd {
+                        low,
+                        high,
+                        transition,
+                        value,
+                    }
+                    | BandStopFd {
+                        low,
+                        high,
+                        transition,
+                        value,
+                    } => (low, high, transition, value),
                 };
                 ui.text("Replace region");
-                ui.radio_button(im_str!("Low"), &mut op_hl, OpHighLow::Low);
-                ui.radio_button(im_str!("High"), &mut op_hl, OpHighLow::High);
-                ui.text("Domain");
-                ui.radio_button(im_str!("Frequency"), &mut op_d, OpDomain::Freq);
-                ui.radio_button(im_str!("Time"), &mut op_d, OpDomain::Time);
+                ui.radio_button(im_str!("Low"), &mut op_region, OpRegion::Low);
+                ui.radio_button(im_str!("High"), &mut op_region, OpRegion::High);
+                ui.radio_button(im_str!("Outside band"), &mut op_region, OpRegion::BandPass);
+                ui.radio_button(im_str!("Inside band"), &mut op_region, OpRegion::BandStop);
+                let band = op_region == OpRegion::BandPass || op_region == OpRegion::BandStop;
+                if band {
+                    op_d = OpDomain::Freq;
+                } else {
+                    ui.text("Domain");
+                    ui.radio_button(im_str!("Frequency"), &mut op_d, OpDomain::Freq);
+                    ui.radio_button(im_str!("Time"), &mut op_d, OpDomain::Time);
+                }
                 Slider::new(
-                    im_str!("Threshold"),
+                    if band {
+                        im_str!("Low (Hz)")
+                    } else {
+                        im_str!("Threshold")
+                    },
                     std::ops::RangeInclusive::new(0.0, 2000.0),
                 )
                 .display_format(im_str!("%0.2f"))
-                .build(ui, &mut threshold);
+                .build(ui, &mut low);
+                if band {
+                    Slider::new(
+                        im_str!("High (Hz)"),
+                        std::ops::RangeInclusive::new(0.0, 20000.0),
+                    )
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, &mut high);
+                }
+                Slider::new(
+                    im_str!("Transition width"),
+                    std::ops::RangeInclusive::new(0.0, 1000.0),
+                )
+                .display_format(im_str!("%0.2f"))
+                .build(ui, &mut transition);
                 Slider::new(
                     im_str!("Amplitude"),
                     std::ops::RangeInclusive::new(0.0, 2.0),
                 )
                 .display_format(im_str!("%0.2f"))
                 .build(ui, &mut value);
-                *self.op_mut() = match op_hl {
-                    OpHighLow::High => match op_d {
-                        OpDomain::Time => ReplaceHigherAmplitudesTd {
-                            threshold: threshold as usize,
-                            value,
-                        },
-                        OpDomain::Freq => ReplaceHigherAmplitudesFd { threshold, value },
+                *self.op_mut() = match (op_region, op_d) {
+                    (OpRegion::High, OpDomain::Time) => ReplaceHigherAmplitudesTd {
+                        threshold: low as usize,
+                        value,
+                        transition: transition as usize,
+                    },
+                    (OpRegion::High, OpDomain::Freq) => ReplaceHigherAmplitudesFd {
+                        threshold: low,
+                        value,
+                        transition,
+                    },
+                    (OpRegion::Low, OpDomain::Time) => ReplaceLowerAmplitudesTd {
+                        threshold: low as usize,
+                        value,
+                        transition: transition as usize,
+                    },
+                    (OpRegion::Low, OpDomain::Freq) => ReplaceLowerAmplitudesFd {
+                        threshold: low,
+                        value,
+                        transition,
+                    },
+                    (OpRegion::BandPass, _) => BandPassFd {
+                        low,
+                        high: high.max(low),
+                        value,
+                        transition,
                     },
-                    OpHighLow::Low => match op_d {
-                        OpDomain::Time => ReplaceLowerAmplitudesTd {
-                            threshold: threshold as usize,
-                            value,
-                        },
-                        OpDomain::Freq => ReplaceLowerAmplitudesFd { threshold, value },
+                    (OpRegion::BandStop, _) => BandStopFd {
+                        low,
+                        high: high.max(low),
+                        value,
+                        transition,
                     },
                 };
             });
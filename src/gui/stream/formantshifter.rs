@@ -1,6 +1,6 @@
 use super::*;
 use crate::audio::stream::{
-    formantshifter::{FormantShifter, Shift},
+    formantshifter::{FormantMode, FormantShifter, Shift, FORMANT_PRESETS},
     node::NodeTrait,
 };
 use imgui::*;
@@ -29,6 +29,24 @@ impl FormantShifter {
             .always_auto_resize(true)
             .position(mouse_pos, Condition::Once)
             .build(&ui, || {
+                ui.radio_button(im_str!("Warp"), self.mode_mut(), FormantMode::Warp);
+                ui.same_line(0.0);
+                ui.radio_button(im_str!("Shifts"), self.mode_mut(), FormantMode::Shifts);
+                let shifts_mode = self.mode() == FormantMode::Shifts;
+                if shifts_mode {
+                    for (i, preset) in FORMANT_PRESETS.iter().enumerate() {
+                        if i > 0 {
+                            ui.same_line(0.0);
+                        }
+                        if ui.small_button(&ImString::new(preset.name())) {
+                            self.apply_preset(*preset);
+                        }
+                    }
+                } else {
+                    Slider::new(im_str!("warp"), std::ops::RangeInclusive::new(0.5, 2.0))
+                        .display_format(im_str!("%0.2f"))
+                        .build(ui, self.warp_mut());
+                }
                 let cursor_pos = ui.cursor_screen_pos();
                 ui.plot_lines(im_str!(""), self.prev_envelope())
                     .graph_size([w, h])
@@ -36,7 +54,7 @@ impl FormantShifter {
                 let pos_x = mouse_pos[0] - cursor_pos[0];
                 let d_f = self.prev_delta_f();
                 let chunk_duration = self.prev_duration().unwrap_or(1024) as f32;
-                let hovered = ui.is_item_hovered();
+                let hovered = ui.is_item_hovered() && shifts_mode;
                 let delta = ui.mouse_drag_delta_with_threshold(MouseButton::Left, 0.0)[0];
                 if delta != 0.0 && ui.is_mouse_down(MouseButton::Left) && hovered {
                     let drag_to = pos_x / w * chunk_duration * d_f;
@@ -55,7 +73,8 @@ impl FormantShifter {
                     let to = pos_x / w * chunk_duration * d_f;
                     self.add_shift(Shift { from, to });
                 }
-                for shift in self.shifts().iter() {
+                let shown: &[Shift] = if shifts_mode { self.shifts() } else { &[] };
+                for shift in shown.iter() {
                     let from_pos_x = shift.from / d_f / chunk_duration * w + cursor_pos[0];
                     let to_pos_x = shift.to / d_f / chunk_duration * w + cursor_pos[0];
                     let min_y = cursor_pos[1];
@@ -77,6 +96,33 @@ impl FormantShifter {
                         .thickness(1.0)
                         .build();
                 }
+                if shifts_mode {
+                    let mut removed = None;
+                    for (i, shift) in self.shifts_mut().iter_mut().enumerate() {
+                        ui.input_float(&im_str!("from (Hz)##{}", i), &mut shift.from)
+                            .build();
+                        ui.input_float(&im_str!("to (Hz)##{}", i), &mut shift.to)
+                            .build();
+                        if ui.small_button(&im_str!("Remove##{}", i)) {
+                            removed = Some(i);
+                        }
+                    }
+                    if let Some(i) = removed {
+                        self.shifts_mut().remove(i);
+                    }
+                    if ui.small_button(im_str!("Add shift")) {
+                        self.add_shift(Shift {
+                            from: 1000.0,
+                            to: 1000.0,
+                        });
+                    }
+                }
+                Slider::new(
+                    im_str!("envelope lifter (ms)"),
+                    std::ops::RangeInclusive::new(0.5, 5.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.lifter_ms_mut());
             });
     }
 }
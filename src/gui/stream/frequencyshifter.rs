@@ -0,0 +1,40 @@
+use super::*;
+use crate::audio::stream::{frequencyshifter::FrequencyShifterNode, node::NodeTrait};
+use imgui::*;
+
+impl InputHandler for FrequencyShifterNode {}
+
+impl FrequencyShifterNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Frequency Shifter".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Frequency Shifter {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                Slider::new(
+                    im_str!("shift (Hz)"),
+                    std::ops::RangeInclusive::new(-2000.0, 2000.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.shift_mut());
+                Slider::new(im_str!("mix"), std::ops::RangeInclusive::new(0.0, 1.0))
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, self.mix_mut());
+                ui.text("Harmonics are no longer multiples of the fundamental after a shift.");
+            });
+    }
+}
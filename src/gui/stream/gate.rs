@@ -0,0 +1,74 @@
+use super::*;
+use crate::audio::stream::{gate::NoiseGateNode, node::NodeTrait};
+use imgui::*;
+
+impl InputHandler for NoiseGateNode {}
+
+impl NoiseGateNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Noise Gate".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Noise Gate {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                let overlay = match self.gain_history().last() {
+                    Some(g) => format!("{:.1} dB", g),
+                    None => "".to_string(),
+                };
+                ui.plot_lines(im_str!("gain reduction"), self.gain_history())
+                    .overlay_text(&ImString::new(overlay))
+                    .scale_min(-80.0)
+                    .scale_max(0.0)
+                    .graph_size([400.0, 100.0])
+                    .build();
+                Slider::new(
+                    im_str!("threshold (dB)"),
+                    std::ops::RangeInclusive::new(-100.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.threshold_mut());
+                Slider::new(im_str!("ratio"), std::ops::RangeInclusive::new(1.0, 100.0))
+                    .display_format(im_str!("%0.1f"))
+                    .build(ui, self.ratio_mut());
+                Slider::new(
+                    im_str!("hysteresis (dB)"),
+                    std::ops::RangeInclusive::new(0.0, 20.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.hysteresis_mut());
+                Slider::new(
+                    im_str!("attack (ms)"),
+                    std::ops::RangeInclusive::new(0.0, 100.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.attack_mut());
+                Slider::new(
+                    im_str!("hold (ms)"),
+                    std::ops::RangeInclusive::new(0.0, 500.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.hold_mut());
+                Slider::new(
+                    im_str!("release (ms)"),
+                    std::ops::RangeInclusive::new(1.0, 2000.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.release_mut());
+                ui.text("The second input, when connected, keys the gate.");
+            });
+    }
+}
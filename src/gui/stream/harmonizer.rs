@@ -0,0 +1,129 @@
+use super::*;
+use crate::audio::pitch::{Scale, NOTE_NAMES};
+use crate::audio::stream::{
+    harmonizer::{HarmonizerNode, HarmonyVoice, IntervalMode},
+    node::NodeTrait,
+};
+use imgui::*;
+
+impl InputHandler for HarmonizerNode {}
+
+impl HarmonizerNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Harmonizer".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Harmonizer {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                Slider::new(im_str!("dry"), std::ops::RangeInclusive::new(0.0, 1.0))
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, self.dry_gain_mut());
+                ui.text("Intervals");
+                ui.radio_button(
+                    im_str!("Semitones"),
+                    self.interval_mode_mut(),
+                    IntervalMode::Semitones,
+                );
+                ui.same_line(0.0);
+                ui.radio_button(
+                    im_str!("Scale degrees"),
+                    self.interval_mode_mut(),
+                    IntervalMode::ScaleDegrees,
+                );
+                let scale_degrees = *self.interval_mode() == IntervalMode::ScaleDegrees;
+                if scale_degrees {
+                    ui.text("Key");
+                    for (i, name) in NOTE_NAMES.iter().enumerate() {
+                        if i > 0 {
+                            ui.same_line(0.0);
+                        }
+                        ui.radio_button(&im_str!("{}##key", name), self.key_mut(), i);
+                    }
+                    ui.text("Scale");
+                    ui.radio_button(im_str!("Major"), self.scale_mut(), Scale::Major);
+                    ui.same_line(0.0);
+                    ui.radio_button(im_str!("Minor"), self.scale_mut(), Scale::Minor);
+                    ui.same_line(0.0);
+                    ui.radio_button(im_str!("Custom"), self.scale_mut(), Scale::Custom);
+                    if *self.scale() == Scale::Custom {
+                        let key = *self.key();
+                        for i in 0..12 {
+                            if i > 0 {
+                                ui.same_line(0.0);
+                            }
+                            let name = NOTE_NAMES[(i + key) % 12];
+                            ui.checkbox(
+                                &im_str!("{}##note", name),
+                                &mut self.custom_notes_mut()[i],
+                            );
+                        }
+                    }
+                }
+                ui.separator();
+
+                let mut removed = None;
+                for (i, voice) in self.voices_mut().iter_mut().enumerate() {
+                    ui.text(format!("Voice {}", i + 1));
+                    ui.same_line(0.0);
+                    if ui.small_button(&im_str!("Remove##{}", i)) {
+                        removed = Some(i);
+                    }
+                    let (interval_range, interval_format) = if scale_degrees {
+                        (std::ops::RangeInclusive::new(-7.0, 7.0), im_str!("%0.0f"))
+                    } else {
+                        (std::ops::RangeInclusive::new(-12.0, 12.0), im_str!("%0.1f"))
+                    };
+                    Slider::new(&im_str!("interval##{}", i), interval_range)
+                        .display_format(interval_format)
+                        .build(ui, &mut voice.interval);
+                    Slider::new(
+                        &im_str!("gain##{}", i),
+                        std::ops::RangeInclusive::new(0.0, 1.0),
+                    )
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, &mut voice.gain);
+                    Slider::new(
+                        &im_str!("pan##{}", i),
+                        std::ops::RangeInclusive::new(-1.0, 1.0),
+                    )
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, &mut voice.pan);
+                    Slider::new(
+                        &im_str!("detune (cents)##{}", i),
+                        std::ops::RangeInclusive::new(-50.0, 50.0),
+                    )
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, &mut voice.detune);
+                }
+                if let Some(i) = removed {
+                    self.remove_voice(i);
+                }
+                if ui.small_button(im_str!("Add voice")) {
+                    self.voices_mut().push(HarmonyVoice::new(0.0));
+                }
+
+                ui.separator();
+                ui.text("Pitch range (Hz)");
+                Slider::new(im_str!("min"), std::ops::RangeInclusive::new(20.0, 500.0))
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, self.min_frequency_mut());
+                Slider::new(im_str!("max"), std::ops::RangeInclusive::new(100.0, 2000.0))
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, self.max_frequency_mut());
+            });
+    }
+}
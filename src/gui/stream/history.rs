@@ -0,0 +1,427 @@
+use super::NodeEditorState;
+use crate::audio::stream::graph::Subgraph;
+use crate::audio::stream::node::*;
+use std::collections::HashMap;
+use std::error::Error;
+
+// a serialized copy of some nodes along with every edge touching them
+#[derive(Debug)]
+pub struct NodeSnapshot {
+    ids: Vec<NodeId>,
+    subgraph: String,
+    edges: Vec<(OutputPortId, InputPortId)>,
+    node_pos: HashMap<NodeId, [f32; 2]>,
+}
+
+impl NodeSnapshot {
+    pub fn capture(state: &NodeEditorState, ids: &[NodeId]) -> Result<Self, Box<dyn Error>> {
+        let g = state.graph();
+        let g = g.lock().unwrap();
+        let subgraph = g.subgraph(ids)?;
+        let ids = subgraph.nodes().iter().map(|n| n.id()).collect::<Vec<_>>();
+        let edges = g
+            .edges()
+            .iter()
+            .filter(|(o, i)| {
+                g.output_port_node(o).map(|n| ids.contains(&n)).unwrap_or(false)
+                    || g.input_port_node(i).map(|n| ids.contains(&n)).unwrap_or(false)
+            })
+            .map(|(o, i)| (*o, *i))
+            .collect();
+        let node_pos = ids
+            .iter()
+            .flat_map(|id| state.node_pos(id).map(|p| (*id, *p)))
+            .collect();
+        Ok(Self {
+            ids,
+            subgraph: serde_json::to_string(&subgraph)?,
+            edges,
+            node_pos,
+        })
+    }
+
+    pub fn ids(&self) -> &[NodeId] {
+        &self.ids
+    }
+
+    fn insert(&self, state: &mut NodeEditorState) -> Result<(), Box<dyn Error>> {
+        let subgraph: Subgraph = serde_json::from_str(&self.subgraph)?;
+        {
+            let g = state.graph();
+            let mut g = g.lock().unwrap();
+            g.insert(subgraph)?;
+            for (o, i) in self.edges.iter() {
+                if g.is_output_port(o) && g.is_input_port(i) && g.edges().get(o) != Some(i) {
+                    g.connect_ports(o, i)?;
+                }
+            }
+        }
+        for (id, pos) in self.node_pos.iter() {
+            state.set_node_pos(*id, *pos);
+        }
+        Ok(())
+    }
+
+    fn remove(&self, state: &mut NodeEditorState) -> Result<(), Box<dyn Error>> {
+        {
+            let g = state.graph();
+            let mut g = g.lock().unwrap();
+            for id in self.ids.iter() {
+                g.node(id)?;
+            }
+            for id in self.ids.iter() {
+                g.remove(*id);
+            }
+        }
+        state.remove_stale_entries();
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum Command {
+    InsertNodes(NodeSnapshot),
+    RemoveNodes(NodeSnapshot),
+    ConnectPorts {
+        from: OutputPortId,
+        to: InputPortId,
+        replaced: Vec<(OutputPortId, InputPortId)>,
+    },
+    DisconnectPorts {
+        from: OutputPortId,
+        to: InputPortId,
+    },
+    MoveNodes {
+        before: HashMap<NodeId, [f32; 2]>,
+        after: HashMap<NodeId, [f32; 2]>,
+    },
+    ChangeNode {
+        id: NodeId,
+        before: String,
+        after: String,
+    },
+}
+
+impl Command {
+    // the edges that connecting `from` to `to` would replace
+    pub fn connect_ports(state: &NodeEditorState, from: OutputPortId, to: InputPortId) -> Self {
+        let replaced = state
+            .graph()
+            .lock()
+            .unwrap()
+            .edges()
+            .iter()
+            .filter(|(o, i)| **o == from || **i == to)
+            .map(|(o, i)| (*o, *i))
+            .collect();
+        Command::ConnectPorts { from, to, replaced }
+    }
+
+    pub fn apply(&self, state: &mut NodeEditorState) -> Result<(), Box<dyn Error>> {
+        match self {
+            Command::InsertNodes(snapshot) => snapshot.insert(state),
+            Command::RemoveNodes(snapshot) => snapshot.remove(state),
+            Command::ConnectPorts { from, to, .. } => {
+                state.graph().lock().unwrap().connect_ports(from, to)
+            }
+            Command::DisconnectPorts { from, to } => {
+                state.graph().lock().unwrap().disconnect_ports(from, to)
+            }
+            Command::MoveNodes { after, .. } => {
+                for (id, pos) in after.iter() {
+                    state.set_node_pos(*id, *pos);
+                }
+                Ok(())
+            }
+            Command::ChangeNode { id, after, .. } => Self::restore_node(state, id, after),
+        }
+    }
+
+    pub fn revert(&self, state: &mut NodeEditorState) -> Result<(), Box<dyn Error>> {
+        match self {
+            Command::InsertNodes(snapshot) => snapshot.remove(state),
+            Command::RemoveNodes(snapshot) => snapshot.insert(state),
+            Command::ConnectPorts { from, to, replaced } => {
+                let g = state.graph();
+                let mut g = g.lock().unwrap();
+                g.disconnect_ports(from, to)?;
+                for (o, i) in replaced.iter() {
+                    g.connect_ports(o, i)?;
+                }
+                Ok(())
+            }
+            Command::DisconnectPorts { from, to } => {
+                state.graph().lock().unwrap().connect_ports(from, to)
+            }
+            Command::MoveNodes { before, .. } => {
+                for (id, pos) in before.iter() {
+                    state.set_node_pos(*id, *pos);
+                }
+                Ok(())
+            }
+            Command::ChangeNode { id, before, .. } => Self::restore_node(state, id, before),
+        }
+    }
+
+    // replaces the parameters of a node while keeping its ports and their channels
+    fn restore_node(
+        state: &mut NodeEditorState,
+        id: &NodeId,
+        json: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut restored: Node = serde_json::from_str(json)?;
+        let node = state.graph().lock().unwrap().node(id)?;
+        let mut node = node.lock().unwrap();
+        std::mem::swap(restored.node_io_mut(), node.node_io_mut());
+        *node = restored;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Command>,
+    redo_stack: Vec<Command>,
+    pending_changes: HashMap<NodeId, String>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // records a command which has already been applied
+    pub fn push(&mut self, command: Command) {
+        self.undo_stack.push(command);
+        self.redo_stack.clear();
+    }
+
+    pub fn perform(
+        &mut self,
+        state: &mut NodeEditorState,
+        command: Command,
+    ) -> Result<(), Box<dyn Error>> {
+        command.apply(state)?;
+        self.push(command);
+        Ok(())
+    }
+
+    // a command which fails stays where it was
+    pub fn undo(&mut self, state: &mut NodeEditorState) -> Result<(), Box<dyn Error>> {
+        if let Some(command) = self.undo_stack.last() {
+            command.revert(state)?;
+            let command = self.undo_stack.pop().unwrap();
+            self.redo_stack.push(command);
+        }
+        Ok(())
+    }
+
+    pub fn redo(&mut self, state: &mut NodeEditorState) -> Result<(), Box<dyn Error>> {
+        if let Some(command) = self.redo_stack.last() {
+            command.apply(state)?;
+            let command = self.redo_stack.pop().unwrap();
+            self.undo_stack.push(command);
+        }
+        Ok(())
+    }
+
+    // Parameters are edited in place by the control windows, so changes are detected by
+    // comparing serialized nodes, and merged into one command until the edit is settled
+    // (e.g. a slider is released).
+    pub fn observe_node(&mut self, id: NodeId, before: String, after: String, settled: bool) {
+        if before != after && !self.pending_changes.contains_key(&id) {
+            self.pending_changes.insert(id, before);
+        }
+        if settled {
+            if let Some(before) = self.pending_changes.remove(&id) {
+                if before != after {
+                    self.push(Command::ChangeNode { id, before, after });
+                }
+            }
+        }
+    }
+
+    pub fn observe_moves(
+        &mut self,
+        before: &HashMap<NodeId, [f32; 2]>,
+        after: &HashMap<NodeId, [f32; 2]>,
+    ) {
+        let moved = after
+            .iter()
+            .filter(|(id, pos)| before.get(id).map(|p| p != *pos).unwrap_or(false))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        if moved.is_empty() {
+            return;
+        }
+        self.push(Command::MoveNodes {
+            before: moved.iter().map(|id| (*id, before[id])).collect(),
+            after: moved.iter().map(|id| (*id, after[id])).collect(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::stream::graph::Graph;
+    use crate::audio::stream::psola::PsolaNode;
+    use std::sync::{Arc, Mutex};
+
+    // node parameters without their ports, edges and positions
+    type Fingerprint = (Vec<String>, Vec<String>, Vec<String>);
+
+    fn fingerprint(state: &NodeEditorState) -> Fingerprint {
+        let g = state.graph();
+        let g = g.lock().unwrap();
+        let mut nodes = g
+            .nodes()
+            .values()
+            .map(|n| {
+                let mut value = serde_json::to_value(&*n.lock().unwrap()).unwrap();
+                for (_, fields) in value.as_object_mut().unwrap().iter_mut() {
+                    fields.as_object_mut().unwrap().remove("io");
+                }
+                value.to_string()
+            })
+            .collect::<Vec<_>>();
+        nodes.sort();
+        let mut edges = g
+            .edges()
+            .iter()
+            .map(|e| format!("{:?}", e))
+            .collect::<Vec<_>>();
+        edges.sort();
+        let mut positions = state
+            .node_positions()
+            .iter()
+            .filter(|(id, _)| g.node(id).is_ok())
+            .map(|p| format!("{:?}", p))
+            .collect::<Vec<_>>();
+        positions.sort();
+        (nodes, edges, positions)
+    }
+
+    fn input_output_ports(state: &NodeEditorState) -> (OutputPortId, InputPortId) {
+        let g = state.graph();
+        let g = g.lock().unwrap();
+        let input = g.input_node().unwrap();
+        let output = g.output_node().unwrap();
+        let from = input.lock().unwrap().outputs()[0].id();
+        let to = output.lock().unwrap().inputs()[0].id();
+        (from, to)
+    }
+
+    fn empty_ports(state: &NodeEditorState, id: &NodeId) -> (OutputPortId, InputPortId) {
+        let node = state.graph().lock().unwrap().node(id).unwrap();
+        let node = node.lock().unwrap();
+        let from = node.outputs().iter().find(|p| p.tx.is_none()).unwrap().id();
+        let to = node.inputs().iter().find(|p| p.rx.is_none()).unwrap().id();
+        (from, to)
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let mut state = NodeEditorState::new(Arc::new(Mutex::new(Graph::default())));
+        let mut history = History::new();
+        let mut states = vec![fingerprint(&state)];
+
+        // insert
+        let node = Node::Psola(PsolaNode::new(1.0));
+        let id = node.id();
+        {
+            let g = state.graph();
+            let mut g = g.lock().unwrap();
+            g.add(node);
+            g.add_input(&id).unwrap();
+            g.add_output(&id).unwrap();
+        }
+        state.set_node_pos(id, [10.0, 20.0]);
+        let snapshot = NodeSnapshot::capture(&state, &[id]).unwrap();
+        history.push(Command::InsertNodes(snapshot));
+        states.push(fingerprint(&state));
+
+        // disconnect the input from the output and route it through the node
+        let (from, to) = input_output_ports(&state);
+        let command = Command::DisconnectPorts { from, to };
+        history.perform(&mut state, command).unwrap();
+        states.push(fingerprint(&state));
+        let (node_from, node_to) = empty_ports(&state, &id);
+        let command = Command::connect_ports(&state, from, node_to);
+        history.perform(&mut state, command).unwrap();
+        states.push(fingerprint(&state));
+        let command = Command::connect_ports(&state, node_from, to);
+        history.perform(&mut state, command).unwrap();
+        states.push(fingerprint(&state));
+
+        // move
+        let before = state.node_positions().clone();
+        state.move_node(&id, [5.0, 5.0]);
+        history.observe_moves(&before, state.node_positions());
+        states.push(fingerprint(&state));
+
+        // change
+        let node = state.graph().lock().unwrap().node(&id).unwrap();
+        let before = serde_json::to_string(&*node.lock().unwrap()).unwrap();
+        if let Node::Psola(ref mut n) = *node.lock().unwrap() {
+            *n.ratio_mut() = 1.5;
+        }
+        let after = serde_json::to_string(&*node.lock().unwrap()).unwrap();
+        history.observe_node(id, before, after, true);
+        states.push(fingerprint(&state));
+
+        // remove
+        let snapshot = NodeSnapshot::capture(&state, &[id]).unwrap();
+        history
+            .perform(&mut state, Command::RemoveNodes(snapshot))
+            .unwrap();
+        states.push(fingerprint(&state));
+
+        for expected in states.iter().rev().skip(1) {
+            history.undo(&mut state).unwrap();
+            assert_eq!(fingerprint(&state), *expected);
+        }
+        assert!(!history.can_undo());
+        for expected in states.iter().skip(1) {
+            history.redo(&mut state).unwrap();
+            assert_eq!(fingerprint(&state), *expected);
+        }
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn failed_commands_stay_on_their_stack() {
+        let mut state = NodeEditorState::new(Arc::new(Mutex::new(Graph::default())));
+        let mut history = History::new();
+        let node = Node::Psola(PsolaNode::new(1.0));
+        let id = node.id();
+        state.graph().lock().unwrap().add(node);
+        state.set_node_pos(id, [0.0, 0.0]);
+        let snapshot = NodeSnapshot::capture(&state, &[id]).unwrap();
+        history.push(Command::InsertNodes(snapshot));
+
+        // the node disappears behind the history's back, so undoing cannot remove it
+        state.graph().lock().unwrap().remove(id);
+        assert!(history.undo(&mut state).is_err());
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+
+        // once it is back, the same command can be undone and redone
+        let mut value = serde_json::to_value(&Node::Psola(PsolaNode::new(1.0))).unwrap();
+        value["Psola"]["id"] = serde_json::to_value(id).unwrap();
+        let restored: Node = serde_json::from_value(value).unwrap();
+        state.graph().lock().unwrap().add(restored);
+        history.undo(&mut state).unwrap();
+        assert!(state.graph().lock().unwrap().node(&id).is_err());
+        history.redo(&mut state).unwrap();
+        assert!(state.graph().lock().unwrap().node(&id).is_ok());
+    }
+}
@@ -0,0 +1,59 @@
+use super::*;
+use crate::audio::stream::{limiter::LimiterNode, node::NodeTrait};
+use imgui::*;
+
+impl InputHandler for LimiterNode {}
+
+impl LimiterNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Limiter".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Limiter {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                let overlay = match self.gain_history().last() {
+                    Some(g) => format!("{:.1} dB", g),
+                    None => "".to_string(),
+                };
+                ui.plot_lines(im_str!("gain reduction"), self.gain_history())
+                    .overlay_text(&ImString::new(overlay))
+                    .scale_min(-24.0)
+                    .scale_max(0.0)
+                    .graph_size([400.0, 100.0])
+                    .build();
+                Slider::new(
+                    im_str!("ceiling (dBTP)"),
+                    std::ops::RangeInclusive::new(-24.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.ceiling_mut());
+                Slider::new(
+                    im_str!("look-ahead (ms)"),
+                    std::ops::RangeInclusive::new(0.1, 20.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.lookahead_mut());
+                Slider::new(
+                    im_str!("release (ms)"),
+                    std::ops::RangeInclusive::new(1.0, 1000.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.release_mut());
+                ui.text("The output is delayed by the look-ahead.");
+            });
+    }
+}
@@ -38,6 +38,48 @@ impl Node {
             Node::FormantShifter(node) => {
                 node.render(ui, node_editor_state);
             }
+            Node::PitchTracker(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::Harmonizer(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::TimeStretch(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::Equalizer(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::NoiseGate(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::Compressor(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::Limiter(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::NoiseReduction(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::Vad(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::ChannelVocoder(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::Oscillator(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::Noise(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::RingModulator(node) => {
+                node.render(ui, node_editor_state);
+            }
+            Node::FrequencyShifter(node) => {
+                node.render(ui, node_editor_state);
+            }
         }
     }
 }
@@ -47,17 +89,27 @@ pub trait InputHandler: NodeTrait {
         ui.set_cursor_pos([0.0, 0.0]);
         let win_pos = ui.cursor_screen_pos();
         let pos = state.node_pos(&self.id()).unwrap().clone();
+        let zoom = state.zoom();
+        // the text is already scaled by the window font scale
         let text_size = ui.calc_text_size(&im_str!("{}", name), false, 1000.0);
-        let (padding_x, padding_y) = (10.0, 5.0);
+        let (padding_x, padding_y) = (10.0 * zoom, 5.0 * zoom);
         let (w, h) = (text_size[0] + 2.0 * padding_x, text_size[1] + 2.0 * padding_y);
+        state.set_node_size(self.id(), [w / zoom, h / zoom]);
         {
             let draw_list = ui.get_window_draw_list();
-            let pos = [pos[0] + win_pos[0], pos[1] + win_pos[1]];
+            let pos = state.to_screen(win_pos, pos);
             draw_list
                 .add_rect(pos, [pos[0] + w, pos[1] + h], (0.9, 0.9, 1.0, 0.8))
                 .rounding(4.0)
                 .filled(true)
                 .build();
+            if state.is_selected(&self.id()) {
+                draw_list
+                    .add_rect(pos, [pos[0] + w, pos[1] + h], (1.0, 0.8, 0.3, 1.0))
+                    .rounding(4.0)
+                    .thickness(2.0)
+                    .build();
+            }
             draw_list.add_text([pos[0] + w / 2.0 - text_size[0] / 2.0, pos[1] + padding_y], (0.0, 0.0, 0.0, 1.0), name);
         }
 
@@ -69,7 +121,10 @@ pub trait InputHandler: NodeTrait {
         let l = self.inputs().len();
         for input in self.inputs().iter() {
             if !(input.rx.is_some() && input.output_id.is_none()) {
-                state.set_input_pos(input.id(), [pos[0] + divide(w, l, i), pos[1] - 5.0]);
+                state.set_input_pos(
+                    input.id(),
+                    [pos[0] + divide(w / zoom, l, i), pos[1] - 5.0 / zoom],
+                );
                 i += 1;
             }
         }
@@ -78,7 +133,10 @@ pub trait InputHandler: NodeTrait {
         let l = self.outputs().len();
         for output in self.outputs().iter() {
             if !(output.tx.is_some() && output.input_id.is_none()) {
-                state.set_output_pos(output.id(), [pos[0] + divide(w, l, i), pos[1] + h]);
+                state.set_output_pos(
+                    output.id(),
+                    [pos[0] + divide(w / zoom, l, i), pos[1] + h / zoom],
+                );
                 i += 1;
             }
         }
@@ -87,8 +145,8 @@ pub trait InputHandler: NodeTrait {
 
     fn handle_input(&mut self, ui: &Ui, state: &mut NodeEditorState, size: [f32; 2]) -> bool {
         let win_pos = ui.cursor_screen_pos();
-        let pos = state.node_pos(&self.id()).unwrap();
-        let screen_pos = [pos[0] + win_pos[0], pos[1] + win_pos[1]];
+        let pos = state.node_pos(&self.id()).unwrap().clone();
+        let screen_pos = state.to_screen(win_pos, pos);
         ui.set_cursor_screen_pos(screen_pos);
         let clicked = ui.invisible_button(&im_str!("{:?}", self.id()), size);
         let hovered = ui.is_item_hovered();
@@ -98,19 +156,44 @@ pub trait InputHandler: NodeTrait {
         let this_left_dragged = state.left_dragged() == Some(self.id());
         {
             let dragging = ui.is_mouse_dragging_with_threshold(MouseButton::Left, 2.0);
-            if dragging && hovered {
+            if dragging
+                && hovered
+                && state.left_dragged().is_none()
+                && state.box_select_origin().is_none()
+            {
                 state.set_left_dragged(Some(self.id()));
             }
             if !dragging {
                 state.set_left_dragged(None);
             }
             if dragging && this_left_dragged {
-                let mouse_pos = ui.io().mouse_pos;
-                let pos = [
-                    mouse_pos[0] - win_pos[0] - size[0] / 2.0,
-                    mouse_pos[1] - win_pos[1] - size[1] / 2.0,
-                ];
-                state.set_node_pos(self.id(), pos);
+                let mouse_delta = ui.io().mouse_delta;
+                let zoom = state.zoom();
+                let delta = [mouse_delta[0] / zoom, mouse_delta[1] / zoom];
+                // a selected node drags the whole selection along
+                if state.is_selected(&self.id()) {
+                    state.move_selected(delta);
+                } else {
+                    state.move_node(&self.id(), delta);
+                }
+            }
+        }
+
+        if hovered && ui.is_mouse_clicked(MouseButton::Right) {
+            state.set_context_menu_node(Some(self.id()));
+            ui.open_popup(im_str!("node context menu"));
+        }
+
+        if clicked && !this_left_dragged {
+            if ui.io().key_ctrl {
+                if state.is_selected(&self.id()) {
+                    state.deselect(&self.id());
+                } else {
+                    state.select(self.id());
+                }
+            } else {
+                state.clear_selection();
+                state.select(self.id());
             }
         }
 
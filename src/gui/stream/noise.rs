@@ -0,0 +1,51 @@
+use super::*;
+use crate::audio::stream::{
+    node::NodeTrait,
+    noise::{NoiseColor, NoiseNode},
+};
+use imgui::*;
+
+impl InputHandler for NoiseNode {}
+
+impl NoiseNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Noise".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Noise {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                let colors = [
+                    ("White", NoiseColor::White),
+                    ("Pink", NoiseColor::Pink),
+                    ("Brown", NoiseColor::Brown),
+                ];
+                for (i, (name, color)) in colors.iter().enumerate() {
+                    if i > 0 {
+                        ui.same_line(0.0);
+                    }
+                    ui.radio_button(&im_str!("{}", name), self.color_mut(), *color);
+                }
+                Slider::new(
+                    im_str!("gain (dB)"),
+                    std::ops::RangeInclusive::new(-60.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.gain_mut());
+                ui.text("Runs at the rate of the audio input.");
+            });
+    }
+}
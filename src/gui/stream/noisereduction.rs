@@ -0,0 +1,95 @@
+use super::*;
+use crate::audio::dynamics::amplitude_to_db;
+use crate::audio::stream::{
+    node::NodeTrait,
+    noisereduction::{NoiseEstimation, NoiseReductionNode},
+};
+use imgui::*;
+
+impl InputHandler for NoiseReductionNode {}
+
+impl NoiseReductionNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Noise Reduction".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Noise Reduction {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                // power in dB of the bins up to the Nyquist frequency
+                let profile = match self.noise_profile() {
+                    Some(profile) => profile
+                        .iter()
+                        .take(profile.len() / 2 + 1)
+                        .map(|p| amplitude_to_db(p.sqrt()))
+                        .collect::<Vec<_>>(),
+                    None => vec![],
+                };
+                ui.plot_lines(im_str!("noise profile"), &profile)
+                    .scale_min(-80.0)
+                    .scale_max(20.0)
+                    .graph_size([400.0, 100.0])
+                    .build();
+                ui.radio_button(
+                    im_str!("Learned"),
+                    self.estimation_mut(),
+                    NoiseEstimation::Learned,
+                );
+                ui.same_line(0.0);
+                ui.radio_button(
+                    im_str!("Minimum statistics"),
+                    self.estimation_mut(),
+                    NoiseEstimation::MinimumStatistics,
+                );
+                match self.estimation() {
+                    NoiseEstimation::Learned => {
+                        if self.learning() {
+                            if ui.small_button(im_str!("Stop learning")) {
+                                self.stop_learning();
+                            }
+                        } else if ui.small_button(im_str!("Learn")) {
+                            self.start_learning();
+                        }
+                        ui.same_line(0.0);
+                        if ui.small_button(im_str!("Discard")) {
+                            self.discard_profile();
+                        }
+                        ui.text("Learn while only the noise is heard.");
+                    }
+                    NoiseEstimation::MinimumStatistics => {
+                        Slider::new(
+                            im_str!("window (ms)"),
+                            std::ops::RangeInclusive::new(200.0, 5000.0),
+                        )
+                        .display_format(im_str!("%0.0f"))
+                        .build(ui, self.window_ms_mut());
+                    }
+                }
+                Slider::new(
+                    im_str!("over-subtraction"),
+                    std::ops::RangeInclusive::new(0.0, 6.0),
+                )
+                .display_format(im_str!("%0.2f"))
+                .build(ui, self.over_subtraction_mut());
+                Slider::new(
+                    im_str!("floor (dB)"),
+                    std::ops::RangeInclusive::new(-60.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.floor_mut());
+            });
+    }
+}
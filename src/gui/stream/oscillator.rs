@@ -0,0 +1,72 @@
+use super::*;
+use crate::audio::stream::{
+    node::NodeTrait,
+    oscillator::{OscillatorNode, Waveform},
+};
+use imgui::*;
+
+impl InputHandler for OscillatorNode {}
+
+impl OscillatorNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Oscillator".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Oscillator {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                ui.plot_lines(im_str!("waveform"), &self.preview(200))
+                    .scale_min(-1.2)
+                    .scale_max(1.2)
+                    .graph_size([400.0, 100.0])
+                    .build();
+                let waveforms = [
+                    ("Sine", Waveform::Sine),
+                    ("Saw", Waveform::Saw),
+                    ("Square", Waveform::Square),
+                    ("Triangle", Waveform::Triangle),
+                    ("Pulse", Waveform::Pulse),
+                ];
+                for (i, (name, waveform)) in waveforms.iter().enumerate() {
+                    if i > 0 {
+                        ui.same_line(0.0);
+                    }
+                    ui.radio_button(&im_str!("{}", name), self.waveform_mut(), *waveform);
+                }
+                Slider::new(
+                    im_str!("frequency (Hz)"),
+                    std::ops::RangeInclusive::new(20.0, 20000.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.frequency_mut());
+                Slider::new(
+                    im_str!("gain (dB)"),
+                    std::ops::RangeInclusive::new(-60.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.gain_mut());
+                if self.waveform() == Waveform::Pulse {
+                    Slider::new(
+                        im_str!("pulse width"),
+                        std::ops::RangeInclusive::new(0.01, 0.99),
+                    )
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, self.pulse_width_mut());
+                }
+                ui.text("Runs at the rate of the audio input.");
+            });
+    }
+}
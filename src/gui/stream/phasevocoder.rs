@@ -1,7 +1,7 @@
 use super::*;
 use crate::audio::stream::{
     node::NodeTrait,
-    phasevocoder::{PhaseVocoder, PitchShiftConfig},
+    phasevocoder::{PhaseLocking, PhaseVocoder, PitchShiftConfig},
 };
 use imgui::*;
 
@@ -64,6 +64,18 @@ impl PhaseVocoder {
                         *config = PitchShiftConfig::Rate(1.0);
                     }
                 }
+                ui.text("Phase locking");
+                ui.radio_button(im_str!("Off"), self.phase_locking_mut(), PhaseLocking::Off);
+                ui.radio_button(
+                    im_str!("Identity"),
+                    self.phase_locking_mut(),
+                    PhaseLocking::Identity,
+                );
+                ui.radio_button(
+                    im_str!("Scaled"),
+                    self.phase_locking_mut(),
+                    PhaseLocking::Scaled,
+                );
             });
     }
 }
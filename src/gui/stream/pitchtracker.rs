@@ -0,0 +1,54 @@
+use super::*;
+use crate::audio::stream::{node::NodeTrait, pitchtracker::PitchTrackerNode};
+use imgui::*;
+
+impl InputHandler for PitchTrackerNode {}
+
+impl PitchTrackerNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Pitch Tracker".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Pitch Tracker {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                let overlay = match self.frequency_history().last() {
+                    Some(f) if *f > 0.0 => format!("{:.1} Hz", f),
+                    Some(_) => "unvoiced".to_string(),
+                    None => "".to_string(),
+                };
+                let overlay = ImString::new(overlay);
+                ui.plot_lines(im_str!("f0"), self.frequency_history())
+                    .overlay_text(&overlay)
+                    .scale_min(self.min_frequency())
+                    .scale_max(self.max_frequency())
+                    .graph_size([400.0, 150.0])
+                    .build();
+                ui.plot_lines(im_str!("confidence"), self.confidence_history())
+                    .scale_min(0.0)
+                    .scale_max(1.0)
+                    .graph_size([400.0, 50.0])
+                    .build();
+                ui.text("Pitch range (Hz)");
+                Slider::new(im_str!("min"), std::ops::RangeInclusive::new(20.0, 500.0))
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, self.min_frequency_mut());
+                Slider::new(im_str!("max"), std::ops::RangeInclusive::new(100.0, 2000.0))
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, self.max_frequency_mut());
+            });
+    }
+}
@@ -10,7 +10,7 @@ impl InputPort {
             let (w, h) = (5.0, 5.0);
             {
                 let draw_list = ui.get_window_draw_list();
-                let pos = [pos[0] + win_pos[0], pos[1] + win_pos[1]];
+                let pos = state.to_screen(win_pos, *pos);
                 draw_list
                     .add_triangle(
                         [pos[0] - w / 2.0, pos[1]],
@@ -34,8 +34,8 @@ impl InputPort {
         size: [f32; 2],
     ) -> Option<ConnectRequest> {
         let win_pos = ui.cursor_screen_pos();
-        let pos = state.input_pos(&self.id()).unwrap();
-        let screen_pos = [pos[0] + win_pos[0] - size[0] / 2.0, pos[1] + win_pos[1]];
+        let pos = state.to_screen(win_pos, *state.input_pos(&self.id()).unwrap());
+        let screen_pos = [pos[0] - size[0] / 2.0, pos[1]];
         ui.set_cursor_screen_pos(screen_pos);
         let clicked = ui.invisible_button(&im_str!("{:?}", self.id()), size);
         let hovered = ui.is_item_hovered();
@@ -67,7 +67,7 @@ impl OutputPort {
             let (w, h) = (5.0, 5.0);
             {
                 let draw_list = ui.get_window_draw_list();
-                let pos = [pos[0] + win_pos[0], pos[1] + win_pos[1]];
+                let pos = state.to_screen(win_pos, *pos);
                 draw_list
                     .add_triangle(
                         [pos[0] - w / 2.0, pos[1]],
@@ -84,8 +84,8 @@ impl OutputPort {
 
     fn handle_input(&self, ui: &Ui, state: &mut NodeEditorState, size: [f32; 2]) {
         let win_pos = ui.cursor_screen_pos();
-        let pos = state.output_pos(&self.id()).unwrap();
-        let screen_pos = [pos[0] + win_pos[0] - size[0] / 2.0, pos[1] + win_pos[1]];
+        let pos = state.to_screen(win_pos, *state.output_pos(&self.id()).unwrap());
+        let screen_pos = [pos[0] - size[0] / 2.0, pos[1]];
         ui.set_cursor_screen_pos(screen_pos);
         let clicked = ui.invisible_button(&im_str!("{:?}", self.id()), size);
         let hovered = ui.is_item_hovered();
@@ -1,4 +1,6 @@
 use super::*;
+use crate::audio::common::WindowFunction;
+use crate::audio::pitch::{Scale, NOTE_NAMES};
 use crate::audio::stream::{node::NodeTrait, psola::PsolaNode};
 use imgui::*;
 
@@ -32,6 +34,81 @@ impl PsolaNode {
                 )
                 .display_format(im_str!("%0.2f"))
                 .build(&ui, self.ratio_mut());
+                ui.same_line(60.0);
+                ui.group(|| {
+                    ui.text("Pitch range (Hz)");
+                    Slider::new(im_str!("min"), std::ops::RangeInclusive::new(20.0, 500.0))
+                        .display_format(im_str!("%0.0f"))
+                        .build(ui, self.min_frequency_mut());
+                    Slider::new(im_str!("max"), std::ops::RangeInclusive::new(100.0, 2000.0))
+                        .display_format(im_str!("%0.0f"))
+                        .build(ui, self.max_frequency_mut());
+                    Slider::new(
+                        im_str!("voicing threshold"),
+                        std::ops::RangeInclusive::new(0.0, 1.0),
+                    )
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, self.voicing_threshold_mut());
+                    ui.text("Window");
+                    ui.radio_button(
+                        im_str!("Hanning"),
+                        self.window_function_mut(),
+                        WindowFunction::Hanning,
+                    );
+                    ui.radio_button(
+                        im_str!("Triangular"),
+                        self.window_function_mut(),
+                        WindowFunction::Triangular,
+                    );
+                    ui.radio_button(
+                        im_str!("Rectangular"),
+                        self.window_function_mut(),
+                        WindowFunction::Rectangular,
+                    );
+                });
+                ui.separator();
+                let correction = self.correction_mut();
+                ui.checkbox(im_str!("pitch correction"), &mut correction.enabled);
+                if correction.enabled {
+                    ui.text("Key");
+                    for (i, name) in NOTE_NAMES.iter().enumerate() {
+                        if i > 0 {
+                            ui.same_line(0.0);
+                        }
+                        ui.radio_button(&im_str!("{}##key", name), &mut correction.key, i);
+                    }
+                    ui.text("Scale");
+                    ui.radio_button(
+                        im_str!("Chromatic"),
+                        &mut correction.scale,
+                        Scale::Chromatic,
+                    );
+                    ui.same_line(0.0);
+                    ui.radio_button(im_str!("Major"), &mut correction.scale, Scale::Major);
+                    ui.same_line(0.0);
+                    ui.radio_button(im_str!("Minor"), &mut correction.scale, Scale::Minor);
+                    ui.same_line(0.0);
+                    ui.radio_button(im_str!("Custom"), &mut correction.scale, Scale::Custom);
+                    if correction.scale == Scale::Custom {
+                        // notes are relative to the key
+                        for i in 0..12 {
+                            if i > 0 {
+                                ui.same_line(0.0);
+                            }
+                            let name = NOTE_NAMES[(i + correction.key) % 12];
+                            ui.checkbox(
+                                &im_str!("{}##note", name),
+                                &mut correction.custom_notes[i],
+                            );
+                        }
+                    }
+                    Slider::new(
+                        im_str!("retune speed (ms)"),
+                        std::ops::RangeInclusive::new(0.0, 500.0),
+                    )
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, &mut correction.retune_speed);
+                }
             });
     }
 }
@@ -0,0 +1,53 @@
+use super::*;
+use crate::audio::stream::{
+    node::NodeTrait, oscillator::Waveform, ringmodulator::RingModulatorNode,
+};
+use imgui::*;
+
+impl InputHandler for RingModulatorNode {}
+
+impl RingModulatorNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Ring Modulator".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Ring Modulator {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                let waveforms = [
+                    ("Sine", Waveform::Sine),
+                    ("Saw", Waveform::Saw),
+                    ("Square", Waveform::Square),
+                    ("Triangle", Waveform::Triangle),
+                ];
+                for (i, (name, waveform)) in waveforms.iter().enumerate() {
+                    if i > 0 {
+                        ui.same_line(0.0);
+                    }
+                    ui.radio_button(&im_str!("{}", name), self.waveform_mut(), *waveform);
+                }
+                Slider::new(
+                    im_str!("frequency (Hz)"),
+                    std::ops::RangeInclusive::new(1.0, 2000.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.frequency_mut());
+                Slider::new(im_str!("mix"), std::ops::RangeInclusive::new(0.0, 1.0))
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, self.mix_mut());
+            });
+    }
+}
@@ -0,0 +1,174 @@
+use super::NodeEditorState;
+use crate::audio::stream::graph::Subgraph;
+use crate::audio::stream::node::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct TemplateNameError(String);
+
+impl Display for TemplateNameError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "\"{}\" is not a valid template name", self.0)
+    }
+}
+
+impl Error for TemplateNameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NodeTemplate {
+    subgraph: Subgraph,
+    node_pos: HashMap<NodeId, [f32; 2]>,
+}
+
+impl NodeTemplate {
+    pub fn from_nodes(state: &NodeEditorState, ids: &[NodeId]) -> Result<Self, Box<dyn Error>> {
+        let subgraph = state.graph().lock().unwrap().subgraph(ids)?;
+        let node_pos = subgraph
+            .nodes()
+            .iter()
+            .flat_map(|n| state.node_pos(&n.id()).map(|p| (n.id(), *p)))
+            .collect();
+        Ok(Self { subgraph, node_pos })
+    }
+
+    pub fn from_selection(state: &NodeEditorState) -> Result<Self, Box<dyn Error>> {
+        let ids = state.selected().iter().cloned().collect::<Vec<_>>();
+        Self::from_nodes(state, &ids)
+    }
+
+    // everything but the Input and Output nodes of a saved project
+    pub fn import_project(path: &str) -> Result<Self, Box<dyn Error>> {
+        let project: NodeEditorState = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let ids = project
+            .graph()
+            .lock()
+            .unwrap()
+            .nodes()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        Self::from_nodes(&project, &ids)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subgraph.is_empty()
+    }
+
+    // top-left corner of the nodes
+    pub fn origin(&self) -> [f32; 2] {
+        if self.node_pos.is_empty() {
+            return [0.0, 0.0];
+        }
+        self.node_pos
+            .values()
+            .fold([f32::MAX; 2], |acc, p| [acc[0].min(p[0]), acc[1].min(p[1])])
+    }
+
+    pub fn library_dir() -> PathBuf {
+        let home = std::env::var("HOME")
+            .or_else(|_| std::env::var("USERPROFILE"))
+            .unwrap_or(".".to_string());
+        PathBuf::from(home).join(".voicething").join("templates")
+    }
+
+    // names become file names in the library, so they must not reach outside of it
+    fn library_path(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        if name.trim().is_empty()
+            || name.contains("..")
+            || name.contains(|c| c == '/' || c == '\\' || c == ':')
+        {
+            return Err(Box::new(TemplateNameError(name.to_string())));
+        }
+        Ok(Self::library_dir().join(format!("{}.json", name)))
+    }
+
+    pub fn library_names() -> Vec<String> {
+        let mut names = match fs::read_dir(Self::library_dir()) {
+            Ok(entries) => entries
+                .flat_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+                .flat_map(|p| p.file_stem().and_then(|s| s.to_str()).map(|s| s.to_string()))
+                .collect::<Vec<_>>(),
+            Err(_) => vec![],
+        };
+        names.sort();
+        names
+    }
+
+    pub fn load(name: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_json(&fs::read_to_string(Self::library_path(name)?)?)
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let path = Self::library_path(name)?;
+        fs::create_dir_all(Self::library_dir())?;
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    fn with_fresh_ids(&self) -> Result<Self, Box<dyn Error>> {
+        // nodes hold channels, so they are copied through their serialized form
+        let copy = Self::from_json(&self.to_json()?)?;
+        let (subgraph, node_ids) = copy.subgraph.with_fresh_ids();
+        let node_pos = copy
+            .node_pos
+            .iter()
+            .flat_map(|(id, p)| node_ids.get(id).map(|id| (*id, *p)))
+            .collect();
+        Ok(Self { subgraph, node_pos })
+    }
+
+    // inserts a copy with fresh ids, placing its top-left corner at `pos`
+    pub fn instantiate(
+        &self,
+        state: &mut NodeEditorState,
+        pos: [f32; 2],
+    ) -> Result<Vec<NodeId>, Box<dyn Error>> {
+        let origin = self.origin();
+        let NodeTemplate { subgraph, node_pos } = self.with_fresh_ids()?;
+        let ids = state.graph().lock().unwrap().insert(subgraph)?;
+        state.clear_selection();
+        for id in ids.iter() {
+            let p = node_pos
+                .get(id)
+                .map(|p| [p[0] - origin[0] + pos[0], p[1] - origin[1] + pos[1]])
+                .unwrap_or(pos);
+            state.set_node_pos(*id, p);
+            state.select(*id);
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn library_path_stays_in_library() {
+        for name in ["", " ", "..", "../x", "a/b", "/x", "a\\b", "C:x"].iter() {
+            assert!(NodeTemplate::library_path(name).is_err(), "{:?}", name);
+        }
+        assert_eq!(
+            NodeTemplate::library_path("deep voice").unwrap(),
+            NodeTemplate::library_dir().join("deep voice.json")
+        );
+    }
+}
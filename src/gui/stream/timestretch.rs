@@ -0,0 +1,49 @@
+use super::*;
+use crate::audio::stream::{node::NodeTrait, timestretch::TimeStretchNode};
+use imgui::*;
+
+impl InputHandler for TimeStretchNode {}
+
+impl TimeStretchNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Time Stretch".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Time Stretch {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                Slider::new(im_str!("stretch"), std::ops::RangeInclusive::new(0.25, 4.0))
+                    .display_format(im_str!("%0.2f"))
+                    .build(ui, self.stretch_mut());
+                Slider::new(
+                    im_str!("window (ms)"),
+                    std::ops::RangeInclusive::new(10.0, 100.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.window_ms_mut());
+                ui.checkbox(im_str!("real time"), self.real_time_mut());
+                if self.real_time() {
+                    Slider::new(
+                        im_str!("max buffer (ms)"),
+                        std::ops::RangeInclusive::new(50.0, 5000.0),
+                    )
+                    .display_format(im_str!("%0.0f"))
+                    .build(ui, self.max_buffer_ms_mut());
+                    ui.text(format!("buffered: {} samples", self.buffered()));
+                }
+            });
+    }
+}
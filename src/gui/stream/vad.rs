@@ -0,0 +1,88 @@
+use super::*;
+use crate::audio::stream::{node::NodeTrait, vad::VadNode};
+use imgui::*;
+
+impl InputHandler for VadNode {}
+
+impl VadNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Voice Activity".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_activity(&self, ui: &Ui, label: &ImStr) {
+        let overlay = if self.active() { "VOICE" } else { "SILENCE" };
+        ui.plot_lines(label, self.activity_history())
+            .overlay_text(&ImString::new(overlay))
+            .scale_min(0.0)
+            .scale_max(1.0)
+            .graph_size([300.0, 30.0])
+            .build();
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Voice Activity {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                self.render_activity(ui, im_str!("activity"));
+                let overlay = match self.noise_floor() {
+                    Some(floor) => format!("noise floor {:.1} dB", floor),
+                    None => "".to_string(),
+                };
+                ui.plot_lines(im_str!("energy (dB)"), self.energy_history())
+                    .overlay_text(&ImString::new(overlay))
+                    .scale_min(-80.0)
+                    .scale_max(0.0)
+                    .graph_size([300.0, 100.0])
+                    .build();
+                Slider::new(
+                    im_str!("energy above floor (dB)"),
+                    std::ops::RangeInclusive::new(0.0, 40.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.energy_threshold_mut());
+                Slider::new(
+                    im_str!("minimum level (dB)"),
+                    std::ops::RangeInclusive::new(-100.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.min_level_mut());
+                Slider::new(
+                    im_str!("zero-crossing rate"),
+                    std::ops::RangeInclusive::new(0.0, 1.0),
+                )
+                .display_format(im_str!("%0.2f"))
+                .build(ui, self.zcr_threshold_mut());
+                Slider::new(
+                    im_str!("spectral flatness (dB)"),
+                    std::ops::RangeInclusive::new(-30.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.flatness_threshold_mut());
+                Slider::new(
+                    im_str!("hangover (ms)"),
+                    std::ops::RangeInclusive::new(0.0, 1000.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.hangover_mut());
+                Slider::new(
+                    im_str!("attenuation (dB)"),
+                    std::ops::RangeInclusive::new(-80.0, 0.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.attenuation_mut());
+                ui.text("The second output carries the decision, the others the gated audio.");
+            });
+    }
+}
@@ -0,0 +1,78 @@
+use super::*;
+use crate::audio::stream::{node::NodeTrait, vocoder::ChannelVocoderNode};
+use imgui::*;
+
+impl InputHandler for ChannelVocoderNode {}
+
+impl ChannelVocoderNode {
+    pub fn render(&mut self, ui: &Ui, state: &mut NodeEditorState) {
+        let size = self.render_node(ui, state, "Channel Vocoder".to_string());
+
+        let clicked = self.handle_input(ui, state, size);
+
+        self.render_control_window(ui, state, clicked);
+    }
+
+    pub fn render_control_window(&mut self, ui: &Ui, state: &mut NodeEditorState, focused: bool) {
+        let opened = state.window_opened(&self.id()).clone();
+        if !opened {
+            return;
+        }
+        let mouse_pos = ui.io().mouse_pos;
+        Window::new(&im_str!("Channel Vocoder {:?}", self.id()))
+            .opened(state.window_opened_mut(&self.id()))
+            .focused(focused)
+            .always_auto_resize(true)
+            .position(mouse_pos, Condition::Once)
+            .build(&ui, || {
+                ui.plot_histogram(im_str!("modulator bands"), &self.modulator_levels())
+                    .scale_min(-80.0)
+                    .scale_max(0.0)
+                    .graph_size([400.0, 100.0])
+                    .build();
+                let mut band_count = self.band_count() as i32;
+                Slider::new(im_str!("bands"), std::ops::RangeInclusive::new(1, 32))
+                    .build(ui, &mut band_count);
+                *self.band_count_mut() = band_count.max(1) as usize;
+                Slider::new(
+                    im_str!("lowest band (Hz)"),
+                    std::ops::RangeInclusive::new(20.0, 1000.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.low_mut());
+                Slider::new(
+                    im_str!("highest band (Hz)"),
+                    std::ops::RangeInclusive::new(1000.0, 16000.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.high_mut());
+                Slider::new(
+                    im_str!("attack (ms)"),
+                    std::ops::RangeInclusive::new(0.0, 100.0),
+                )
+                .display_format(im_str!("%0.1f"))
+                .build(ui, self.attack_mut());
+                Slider::new(
+                    im_str!("release (ms)"),
+                    std::ops::RangeInclusive::new(1.0, 500.0),
+                )
+                .display_format(im_str!("%0.0f"))
+                .build(ui, self.release_mut());
+                ui.checkbox(im_str!("normalize carrier"), self.normalize_carrier_mut());
+                ui.text("Band gains (dB)");
+                for (i, gain) in self.band_gains_mut().iter_mut().enumerate() {
+                    if i > 0 {
+                        ui.same_line(0.0);
+                    }
+                    VerticalSlider::new(
+                        &im_str!("##gain{}", i),
+                        [18.0, 120.0],
+                        std::ops::RangeInclusive::new(-24.0, 24.0),
+                    )
+                    .display_format(im_str!(""))
+                    .build(&ui, gain);
+                }
+                ui.text("The first input is the modulator, the second the carrier.");
+            });
+    }
+}